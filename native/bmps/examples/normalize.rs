@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use bmps::config::Size;
+use bmps::blur::Edge;
+use bmps::config::{
+    Align, Background, BackgroundMode, BlurStyle, Caption, DateStamp, Grain, Length, Padding,
+    Pattern, Side, Size, Span, Tone,
+};
 use bmps::Config;
 use clap::{arg, ArgMatches};
 fn get<T: Clone + Send + Sync + 'static>(m: &ArgMatches, id: &str) -> T {
@@ -28,6 +32,13 @@ fn main() {
                 .default_value("50")
                 .value_parser(clap::value_parser!(u32)),
         )
+        .arg(
+            arg!(--"blur-edge" [EDGE] "mirror, clamp or wrap")
+                .default_value("mirror")
+                .value_parser(["mirror", "clamp", "wrap"])
+                .hide_possible_values(true),
+        )
+        .arg(arg!(--"blur-style" [STYLE] "bokeh, motion:DEG or zoom:X,Y").default_value("gaussian"))
         .arg(
             arg!(-r --round [ROUND] "Round radius")
                 .default_value("45")
@@ -51,27 +62,145 @@ fn main() {
                 .value_parser(clap::value_parser!(i32)),
         )
         .arg(
-            arg!(-p --padding [PADDING] "Range [0, 0.5)")
+            arg!(-p --padding [PADDING] "Ratio, px or %, 1 to 4 values like CSS")
                 .default_value("0.1")
-                .value_parser(clap::value_parser!(f64)),
+                .value_parser(padding),
+        )
+        .arg(arg!(--align [POS] "e.g. top, bottom-left or right").default_value("center"))
+        .arg(
+            arg!(--offset [X_Y] "Photo offset in px or %, e.g. 0,-5%")
+                .allow_negative_numbers(true)
+                .default_value("0,0"),
         )
         .arg(arg!(-i --input [INPUT_PATH] "File or directory path").default_value("."))
         .arg(arg!(-o --out [OUTPUT] "Output path").default_value("."))
         .arg(arg!(-W --"white-bg"  "White background").value_parser(clap::value_parser!(bool)))
+        .arg(
+            arg!(-T --transparent "Transparent canvas, saved as PNG")
+                .value_parser(clap::value_parser!(bool))
+                .conflicts_with_all(["white-bg", "pattern", "bg-mode"]),
+        )
         .arg(
             arg!(--"aspect-ratio"  "If width and height stand for aspect ratio")
                 .value_parser(clap::value_parser!(bool)),
         )
+        .arg(arg!(-f --font [FONT] "Font file path"))
+        .arg(arg!(-c --caption [CAPTION] "Caption text, requires --font"))
+        .arg(
+            arg!(--"caption-side" [SIDE] "left, right, top or bottom")
+                .default_value("bottom")
+                .value_parser(["left", "right", "top", "bottom"])
+                .hide_possible_values(true),
+        )
+        .arg(
+            arg!(--vertical "Vertical caption in right-to-left columns")
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!(--"auto-color" "Caption color by background luminance")
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!(-d --"date-stamp" [FORMAT] "Orange date stamp, e.g. \"'YY MM DD\"")
+                .num_args(0..=1)
+                .default_missing_value("'YY MM DD"),
+        )
+        .arg(
+            arg!(-m --"memory-budget" [MB] "Render in bands within MB of buffers")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            arg!(-l --"linear-light" "Blur and blend in linear light")
+                .value_parser(clap::value_parser!(bool)),
+        )
+        .arg(
+            arg!(--exposure [EV] "Background exposure in stops")
+                .default_value("0")
+                .allow_negative_numbers(true)
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            arg!(--saturation [SAT] "Background saturation, 0 for gray")
+                .default_value("1")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(arg!(--tint [RRGGBBAA] "Background tint, alpha as amount"))
+        .arg(
+            arg!(--vignette [STRENGTH] "Background vignette, range [0, 1]")
+                .default_value("0")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            arg!(-g --grain [STRENGTH] "Background grain in 8-bit levels")
+                .num_args(0..=1)
+                .default_missing_value("1")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            arg!(--"grain-size" [PX] "Grain size, 1 for dithering")
+                .default_value("1")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(
+            arg!(--seed [SEED] "Grain and pattern seed")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .arg(
+            arg!(--"bg-mode" [MODE] "blur, extend, mosaic, duotone or frosted")
+                .default_value("blur"),
+        )
+        .arg(
+            arg!(--pattern [PATTERN] "fractal, noise or mesh background")
+                .value_parser(["fractal", "noise", "mesh"])
+                .hide_possible_values(true),
+        )
+        .arg(arg!(--"bg-image" [PATH] "Background image instead of the photo"))
+        .arg(
+            arg!(--"bg-zoom" [ZOOM] "Background zoom, at least 1")
+                .default_value("1")
+                .value_parser(clap::value_parser!(f32)),
+        )
+        .arg(arg!(--"bg-focus" [X_Y] "Background focus, e.g. 0.5,0.3").default_value("0.5,0.5"))
         .get_matches();
+    let caption = matches.get_one::<String>("caption").map(|text| Caption {
+        spans: vec![Span {
+            text: text.clone(),
+            ..Default::default()
+        }],
+        side: match get::<String>(&matches, "caption-side").as_str() {
+            "left" => Side::Left,
+            "right" => Side::Right,
+            "top" => Side::Top,
+            _ => Side::Bottom,
+        },
+        vertical: get(&matches, "vertical"),
+        auto_color: get::<bool>(&matches, "auto-color").then(Default::default),
+        ..Default::default()
+    });
+    let offset: Vec<Length> = get::<String>(&matches, "offset")
+        .split(',')
+        .map(length)
+        .collect();
     let cfg = Config {
-        font: None,
+        font: matches.get_one::<String>("font").cloned(),
         size: Size {
             width: get(&matches, "width"),
             height: get(&matches, "height"),
             aspect_ratio: get(&matches, "aspect-ratio"),
             blur_radius: get(&matches, "blur"),
+            blur_edge: match get::<String>(&matches, "blur-edge").as_str() {
+                "clamp" => Edge::Clamp,
+                "wrap" => Edge::Wrap,
+                _ => Edge::Mirror,
+            },
+            blur_style: blur_style(get::<String>(&matches, "blur-style").as_str()),
             round_radius: get(&matches, "round"),
             padding: get(&matches, "padding"),
+            align_x: align(get::<String>(&matches, "align").as_str(), "left", "right"),
+            align_y: align(get::<String>(&matches, "align").as_str(), "top", "bottom"),
+            offset_x: offset.first().copied().unwrap_or(Length::Pixels(0)),
+            offset_y: offset.get(1).copied().unwrap_or(Length::Pixels(0)),
             shadow: get(&matches, "shadow"),
             shadow_offset_x: get(&matches, "SHADOW_OFFSET_X"),
             shadow_offset_y: get(&matches, "SHADOW_OFFSET_Y"),
@@ -79,6 +208,53 @@ fn main() {
         source_file: get(&matches, "input"),
         dest_file: get(&matches, "out"),
         white_bg: get(&matches, "white-bg"),
+        caption,
+        date_stamp: matches
+            .get_one::<String>("date-stamp")
+            .map(|format| DateStamp {
+                format: format.clone(),
+                ..Default::default()
+            }),
+        memory_budget: matches.get_one::<usize>("memory-budget").map(|mb| mb << 20),
+        linear_light: get(&matches, "linear-light"),
+        tone: Tone {
+            exposure: get(&matches, "exposure"),
+            saturation: get(&matches, "saturation"),
+            tint: matches
+                .get_one::<String>("tint")
+                .map_or([0; 4], |hex| tint(hex)),
+            vignette: get(&matches, "vignette"),
+        },
+        grain: matches.get_one::<f32>("grain").map(|&strength| Grain {
+            strength,
+            size: get(&matches, "grain-size"),
+            seed: get(&matches, "seed"),
+            ..Default::default()
+        }),
+        background: {
+            let focus: Vec<f32> = get::<String>(&matches, "bg-focus")
+                .split(',')
+                .map(|v| v.trim().parse().expect("Invalid bg-focus"))
+                .collect();
+            Background {
+                mode: match matches.get_one::<String>("pattern").map(String::as_str) {
+                    Some(pattern) => BackgroundMode::Procedural {
+                        pattern: match pattern {
+                            "noise" => Pattern::Noise,
+                            "mesh" => Pattern::Mesh,
+                            _ => Pattern::Fractal,
+                        },
+                        seed: get(&matches, "seed"),
+                    },
+                    None if get(&matches, "transparent") => BackgroundMode::Transparent,
+                    None => bg_mode(get::<String>(&matches, "bg-mode").as_str()),
+                },
+                image: matches.get_one::<String>("bg-image").cloned(),
+                zoom: get(&matches, "bg-zoom"),
+                focus_x: focus.first().copied().unwrap_or(0.5),
+                focus_y: focus.get(1).copied().unwrap_or(0.5),
+            }
+        },
     };
     if is_dir(cfg.source_file.as_str()) {
         batch(&cfg);
@@ -87,6 +263,125 @@ fn main() {
     }
 }
 
+// motion 默认水平，zoom 默认以画布中心为中心
+fn blur_style(s: &str) -> BlurStyle {
+    let (name, args) = s.split_once(':').unwrap_or((s, ""));
+    let nums: Vec<f32> = args
+        .split(',')
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+    match name {
+        "bokeh" => BlurStyle::Bokeh {
+            highlight: nums.first().copied().unwrap_or(4.0),
+        },
+        "motion" => BlurStyle::Motion {
+            angle: nums.first().copied().unwrap_or(0.0),
+        },
+        "zoom" => BlurStyle::Zoom {
+            center_x: nums.first().copied().unwrap_or(0.5),
+            center_y: nums.get(1).copied().unwrap_or(0.5),
+        },
+        _ => BlurStyle::Gaussian,
+    }
+}
+
+// 如 top、bottom-left，未指定的方向居中
+fn align(s: &str, start: &str, end: &str) -> Align {
+    let parts: Vec<&str> = s.split(['-', ',']).map(str::trim).collect();
+    if parts.contains(&start) {
+        Align::Start
+    } else if parts.contains(&end) {
+        Align::End
+    } else {
+        Align::Center
+    }
+}
+
+// 以 % 结尾时为画布宽高的百分比
+fn length(s: &str) -> Length {
+    let s = s.trim();
+    match s.strip_suffix('%') {
+        Some(v) => Length::Ratio(v.parse::<f64>().expect("Invalid offset") / 100.0),
+        None => Length::Pixels(s.trim_end_matches("px").parse().expect("Invalid offset")),
+    }
+}
+
+// 如 0.1、0.05,0.05,0.2 或 40px,5%，按 CSS 的顺序与省略规则：上 右 下 左。
+// 不带单位时为比例
+fn padding(s: &str) -> Result<Padding, String> {
+    let values = s
+        .split(',')
+        .map(|v| {
+            let v = v.trim();
+            let err = || format!("invalid padding `{v}`");
+            if let Some(p) = v.strip_suffix('%') {
+                p.parse::<f64>()
+                    .map(|r| Length::Ratio(r / 100.0))
+                    .map_err(|_| err())
+            } else if let Some(p) = v.strip_suffix("px") {
+                p.parse().map(Length::Pixels).map_err(|_| err())
+            } else {
+                v.parse().map(Length::Ratio).map_err(|_| err())
+            }
+        })
+        .collect::<Result<Vec<Length>, String>>()?;
+    let [top, right, bottom, left] = match *values.as_slice() {
+        [a] => [a; 4],
+        [a, b] => [a, b, a, b],
+        [a, b, c] => [a, b, c, b],
+        [a, b, c, d] => [a, b, c, d],
+        _ => return Err(format!("expected 1 to 4 values, got {}", values.len())),
+    };
+    Ok(Padding {
+        top,
+        right,
+        bottom,
+        left,
+    })
+}
+
+fn bg_mode(s: &str) -> BackgroundMode {
+    let (name, args) = s.split_once(':').unwrap_or((s, ""));
+    match name {
+        "extend" => BackgroundMode::Extend {
+            spread: args.trim().parse().unwrap_or(1.0),
+        },
+        "mosaic" => BackgroundMode::Mosaic {
+            cell: args.trim().parse().unwrap_or(48),
+        },
+        "duotone" => {
+            let colors: Vec<[u8; 3]> = args
+                .split(',')
+                .filter(|v| !v.is_empty())
+                .map(|hex| {
+                    let [r, g, b, _] = tint(hex);
+                    [r, g, b]
+                })
+                .collect();
+            BackgroundMode::Duotone {
+                dark: colors.first().copied(),
+                light: colors.get(1).copied(),
+            }
+        }
+        "frosted" => BackgroundMode::Frosted {
+            saturation: 1.4,
+            noise: 3.0,
+            tint: [255, 255, 255, 40],
+        },
+        _ => BackgroundMode::Blur,
+    }
+}
+
+/// 解析 RRGGBB 或 RRGGBBAA，省略 alpha 时按 50% 叠加
+fn tint(hex: &str) -> [u8; 4] {
+    let hex = hex.trim_start_matches('#');
+    let mut rgba = [0, 0, 0, 128];
+    for (i, v) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        *v = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).expect("Invalid tint");
+    }
+    rgba
+}
+
 fn single(cfg: &Config) {
     let mut output = PathBuf::new();
     output.push(cfg.dest_file.as_str());
@@ -98,7 +393,9 @@ fn single(cfg: &Config) {
         let p = std::path::Path::new(cfg.source_file.as_str());
         let stem = p.file_stem().unwrap();
         let mut s = stem.to_os_string();
-        s.push("_bmps.jpg"); // FIXME: 若文件已存在，加编号
+        // 透明画布需要支持 alpha 的格式
+        let ext = if transparent(cfg) { "png" } else { "jpg" };
+        s.push(format!("_bmps.{ext}")); // FIXME: 若文件已存在，加编号
         output.push(s);
     }
     let mut c = cfg.clone();
@@ -130,6 +427,9 @@ fn batch(cfg: &Config) {
         .collect();
     for f in entries {
         output.push(f.file_name());
+        if transparent(cfg) {
+            output.set_extension("png");
+        }
         let mut c = cfg.clone();
         c.source_file = f.path().to_string_lossy().to_string();
         c.dest_file = output.to_string_lossy().to_string();
@@ -139,3 +439,7 @@ fn batch(cfg: &Config) {
         }
     }
 }
+
+fn transparent(cfg: &Config) -> bool {
+    cfg.background.mode == BackgroundMode::Transparent
+}
@@ -0,0 +1,538 @@
+//! 以三次盒式模糊近似高斯模糊，
+//! 见 [Fastest Gaussian Blur](https://blog.ivank.net/fastest-gaussian-blur.html)。
+//! 横向逐行滑动求和，纵向先转置再按行处理，内存连续便于编译器向量化。
+use rayon::prelude::*;
+
+/// 可参与模糊计算的子像素类型
+pub trait Sample: Copy + Default + Send + Sync {
+    /// 整数类型的最大值，浮点类型为 None
+    const INT_MAX: Option<u32>;
+    fn into_f64(self) -> f64;
+    fn from_f64(v: f64) -> Self;
+}
+
+/// 盒式求和的累加器。整数以回绕加减累加，最终的和不溢出时结果精确
+trait Acc: Copy + Default + Send + Sync {
+    fn from_f64(v: f64) -> Self;
+    fn into_f64(self) -> f64;
+    fn add(self, o: Self) -> Self;
+    fn sub(self, o: Self) -> Self;
+}
+
+macro_rules! int_acc {
+    ($($t:ty),*) => {$(
+        impl Acc for $t {
+            fn from_f64(v: f64) -> Self {
+                v as $t
+            }
+            fn into_f64(self) -> f64 {
+                self as f64
+            }
+            fn add(self, o: Self) -> Self {
+                self.wrapping_add(o)
+            }
+            fn sub(self, o: Self) -> Self {
+                self.wrapping_sub(o)
+            }
+        }
+    )*};
+}
+int_acc!(u32, u64);
+
+impl Acc for f64 {
+    fn from_f64(v: f64) -> Self {
+        v
+    }
+    fn into_f64(self) -> f64 {
+        self
+    }
+    fn add(self, o: Self) -> Self {
+        self + o
+    }
+    fn sub(self, o: Self) -> Self {
+        self - o
+    }
+}
+
+impl Sample for u8 {
+    const INT_MAX: Option<u32> = Some(u8::MAX as u32);
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        // as 转换自带饱和截断，比 round + clamp 快
+        (v + 0.5) as u8
+    }
+}
+
+impl Sample for u16 {
+    const INT_MAX: Option<u32> = Some(u16::MAX as u32);
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        // as 转换自带饱和截断，比 round + clamp 快
+        (v + 0.5) as u16
+    }
+}
+
+impl Sample for f32 {
+    const INT_MAX: Option<u32> = None;
+    fn into_f64(self) -> f64 {
+        self as f64
+    }
+    fn from_f64(v: f64) -> Self {
+        v as f32
+    }
+}
+
+/// 模糊窗口超出图像边缘时的取值方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Edge {
+    // 以边缘为轴镜像，边缘附近与内部一样明亮
+    #[default]
+    Mirror,
+    // 延伸边缘像素
+    Clamp,
+    // 从对侧取值，用于平铺图案
+    Wrap,
+}
+
+impl Edge {
+    /// 将超出 [0, len) 的下标映射回图像内
+    pub(crate) fn index(self, i: isize, len: usize) -> usize {
+        let n = len as isize;
+        match self {
+            Edge::Clamp => i.clamp(0, n - 1) as usize,
+            Edge::Wrap => i.rem_euclid(n) as usize,
+            Edge::Mirror => {
+                if n == 1 {
+                    return 0;
+                }
+                let period = 2 * (n - 1);
+                let m = i.rem_euclid(period);
+                (if m < n { m } else { period - m }) as usize
+            }
+        }
+    }
+}
+
+/// 三次盒式模糊的半径，使总方差接近 sigma^2
+pub fn boxes(sigma: f32) -> [usize; 3] {
+    let n = 3.0;
+    let ideal = (12.0 * sigma * sigma / n + 1.0).sqrt();
+    let mut wl = ideal.floor() as i32;
+    if wl % 2 == 0 {
+        wl -= 1;
+    }
+    let wu = wl + 2;
+    let wlf = wl as f32;
+    let m = ((12.0 * sigma * sigma - n * wlf * wlf - 4.0 * n * wlf - 3.0 * n) / (-4.0 * wlf - 4.0))
+        .round() as i32;
+    let mut radii = [0; 3];
+    for (i, r) in radii.iter_mut().enumerate() {
+        let w = if (i as i32) < m { wl } else { wu };
+        *r = ((w.max(1) - 1) / 2) as usize;
+    }
+    radii
+}
+
+/// 原地高斯模糊，data 为 width x height 个像素，每像素 N 个通道，边缘像素向外延伸
+pub fn gaussian_blur<T: Sample, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    height: usize,
+    sigma: f32,
+) {
+    gaussian_blur_edge::<T, N>(data, width, height, sigma, Edge::Clamp);
+}
+
+/// 原地高斯模糊，按 edge 处理图像边缘
+pub fn gaussian_blur_edge<T: Sample, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    height: usize,
+    sigma: f32,
+    edge: Edge,
+) {
+    assert_eq!(data.len(), width * height * N);
+    if sigma <= 0.0 || data.is_empty() {
+        return;
+    }
+    let radii = boxes(sigma);
+    horizontal::<T, N>(data, width, radii, edge);
+    let mut t = vec![T::default(); data.len()];
+    transpose::<T, N>(data, &mut t, width, height);
+    horizontal::<T, N>(&mut t, height, radii, edge);
+    transpose::<T, N>(&t, data, height, width);
+}
+
+/// 原地模糊带透明度的图像，最后一个通道为 alpha。
+/// 先以 alpha 预乘颜色再模糊，之后除以模糊后的 alpha 还原，
+/// 透明像素的颜色不会渗入相邻的不透明像素
+pub fn gaussian_blur_premultiplied<T: Sample, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    height: usize,
+    sigma: f32,
+) {
+    assert_eq!(data.len(), width * height * N);
+    if sigma <= 0.0 || data.is_empty() {
+        return;
+    }
+    let mut p = vec![0f32; data.len()];
+    p.par_chunks_mut(N)
+        .zip(data.par_chunks(N))
+        .for_each(|(p, s)| {
+            let a = s[N - 1].into_f64();
+            for c in 0..N - 1 {
+                p[c] = (s[c].into_f64() * a) as f32;
+            }
+            p[N - 1] = a as f32;
+        });
+    gaussian_blur::<f32, N>(&mut p, width, height, sigma);
+    data.par_chunks_mut(N)
+        .zip(p.par_chunks(N))
+        .for_each(|(s, p)| {
+            let a = p[N - 1] as f64;
+            for c in 0..N - 1 {
+                s[c] = if a > 0.0 {
+                    T::from_f64(p[c] as f64 / a)
+                } else {
+                    T::default()
+                };
+            }
+            s[N - 1] = T::from_f64(a);
+        });
+}
+
+/// 纵向模糊影响的范围（行数），分条计算时每条上下需多取这么多行
+pub fn halo(sigma: f32) -> usize {
+    if sigma <= 0.0 {
+        return 0;
+    }
+    boxes(sigma).iter().sum()
+}
+
+/// 按内存预算 budget（字节）确定的条高，row_bytes 为每行所需的缓冲。
+/// 每条上下还需各多取 halo 行，另需暂存上一条末尾的 halo 行。budget 为 None 时整幅计算
+pub fn band_rows(budget: Option<usize>, row_bytes: usize, halo: usize, height: usize) -> usize {
+    match budget {
+        Some(b) => (b / row_bytes.max(1)).saturating_sub(halo * 3).max(1),
+        None => height,
+    }
+}
+
+/// 分条原地处理 data，每行 stride 个元素，每条 band 行。
+/// f(ext, start, dst, y0) 中 ext 为本条连同上下 halo 行处理前的数据，自整幅第 start 行起，
+/// dst 为整幅第 y0 行起的本条各行，f 将结果写入 dst
+pub fn for_each_band<T, F>(data: &mut [T], stride: usize, band: usize, halo: usize, mut f: F)
+where
+    T: Copy,
+    F: FnMut(&mut [T], usize, &mut [T], usize),
+{
+    let height = data.len() / stride.max(1);
+    // 上一条末尾 halo 行处理前的数据
+    let mut above: Vec<T> = vec![];
+    let mut y0 = 0;
+    while y0 < height {
+        let y1 = (y0 + band).min(height);
+        let start = y0.saturating_sub(halo);
+        let end = (y1 + halo).min(height);
+        let mut ext = Vec::with_capacity((end - start) * stride);
+        ext.extend_from_slice(&above);
+        ext.extend_from_slice(&data[y0 * stride..end * stride]);
+        if y1 < height {
+            let next = y1.saturating_sub(halo);
+            above = ext[(next - start) * stride..(y1 - start) * stride].to_vec();
+        }
+        f(&mut ext, start, &mut data[y0 * stride..y1 * stride], y0);
+        y0 = y1;
+    }
+}
+
+/// 分条原地高斯模糊，每条连同上下 halo 行一同计算，结果与 gaussian_blur_edge 一致。
+/// 条高按 budget（字节）确定，budget 为 None 时整幅计算。
+/// Wrap 需要对侧的行，总是整幅计算
+pub fn gaussian_blur_banded<T: Sample, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    height: usize,
+    sigma: f32,
+    edge: Edge,
+    budget: Option<usize>,
+) {
+    let halo = halo(sigma);
+    let stride = width * N;
+    // 每行需要条带副本与转置缓冲两份
+    let row_bytes = stride * std::mem::size_of::<T>() * 2;
+    let band = band_rows(budget, row_bytes, halo, height);
+    if band >= height || edge == Edge::Wrap {
+        gaussian_blur_edge::<T, N>(data, width, height, sigma, edge);
+        return;
+    }
+    for_each_band(data, stride, band, halo, |ext, start, dst, y0| {
+        gaussian_blur_edge::<T, N>(ext, width, ext.len() / stride, sigma, edge);
+        dst.copy_from_slice(&ext[(y0 - start) * stride..][..dst.len()]);
+    });
+}
+
+/// 逐行做三次盒式模糊。中间结果保留窗口内的和而不做除法，
+/// 整数输入以整数累加，全程精确，分条计算与整幅计算的结果因此完全一致。
+/// 和的上限放得进 u32 时用 u32，8 位图像常见半径下均如此，否则用 u64；
+/// 浮点图像用 f64，f32 的滑动和在长行上误差累积
+fn horizontal<T: Sample, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    radii: [usize; 3],
+    edge: Edge,
+) {
+    let div: f64 = radii.iter().map(|&r| (2 * r + 1) as f64).product();
+    match T::INT_MAX {
+        Some(max) if max as f64 * div <= u32::MAX as f64 => {
+            horizontal_with::<T, u32, N>(data, width, radii, edge, div)
+        }
+        Some(_) => horizontal_with::<T, u64, N>(data, width, radii, edge, div),
+        None => horizontal_with::<T, f64, N>(data, width, radii, edge, div),
+    }
+}
+
+fn horizontal_with<T: Sample, A: Acc, const N: usize>(
+    data: &mut [T],
+    width: usize,
+    radii: [usize; 3],
+    edge: Edge,
+    div: f64,
+) {
+    let stride = width * N;
+    let r_max = radii.iter().copied().max().unwrap_or(0);
+    data.par_chunks_mut(stride).for_each_init(
+        || {
+            (
+                vec![A::default(); stride],
+                vec![A::default(); stride],
+                Vec::with_capacity(stride + (2 * r_max + 2) * N),
+            )
+        },
+        |(a, b, pad), row| {
+            a.iter_mut()
+                .zip(row.iter())
+                .for_each(|(v, s)| *v = A::from_f64(s.into_f64()));
+            for r in radii {
+                box_line::<A, N>(a, b, pad, r, edge);
+                std::mem::swap(a, b);
+            }
+            row.iter_mut()
+                .zip(a.iter())
+                .for_each(|(s, v)| *s = T::from_f64(v.into_f64() / div));
+        },
+    );
+}
+
+/// 单行盒式求和。先按 edge 在两侧各补 r + 1 个像素，内层循环不再判断边界
+fn box_line<A: Acc, const N: usize>(
+    src: &[A],
+    dst: &mut [A],
+    pad: &mut Vec<A>,
+    r: usize,
+    edge: Edge,
+) {
+    let len = src.len() / N;
+    let side = r as isize + 1;
+    let extend = |range: std::ops::Range<isize>, pad: &mut Vec<A>| {
+        for k in range {
+            let j = edge.index(k, len) * N;
+            pad.extend_from_slice(&src[j..j + N]);
+        }
+    };
+    pad.clear();
+    extend(-side..0, pad);
+    pad.extend_from_slice(src);
+    extend(len as isize..len as isize + side, pad);
+    // pad 的第 i + r + 1 个像素即 src 的第 i 个，输出 i 的窗口为 pad[i + 1 ..= i + 2r + 1]
+    let mut acc = [A::default(); N];
+    for p in pad[N..(2 * r + 2) * N].chunks_exact(N) {
+        for c in 0..N {
+            acc[c] = acc[c].add(p[c]);
+        }
+    }
+    let adds = pad[(2 * r + 2) * N..].chunks_exact(N);
+    let subs = pad[N..].chunks_exact(N);
+    for ((out, add), sub) in dst.chunks_exact_mut(N).zip(adds).zip(subs) {
+        out.copy_from_slice(&acc);
+        for c in 0..N {
+            acc[c] = acc[c].add(add[c]).sub(sub[c]);
+        }
+    }
+}
+
+/// 分块转置，dst 的第 x 行为 src 的第 x 列
+fn transpose<T: Sample, const N: usize>(src: &[T], dst: &mut [T], width: usize, height: usize) {
+    const BLOCK: usize = 16;
+    dst.par_chunks_mut(height * N * BLOCK)
+        .enumerate()
+        .for_each(|(b, block)| {
+            let x0 = b * BLOCK;
+            let rows = block.len() / (height * N);
+            for y in 0..height {
+                let line = &src[(y * width + x0) * N..(y * width + x0 + rows) * N];
+                for (dx, p) in line.chunks_exact(N).enumerate() {
+                    let d = (dx * height + y) * N;
+                    block[d..d + N].copy_from_slice(p);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variance() {
+        for sigma in [1.0, 2.5, 10.0, 25.0, 60.0] {
+            let v: f32 = boxes(sigma)
+                .iter()
+                .map(|&r| ((2 * r + 1).pow(2) - 1) as f32 / 12.0)
+                .sum();
+            assert!((v.sqrt() - sigma).abs() < sigma * 0.1 + 0.5, "{sigma}");
+        }
+    }
+
+    #[test]
+    fn flat() {
+        let mut a = vec![200u8; 30 * 20 * 3];
+        gaussian_blur::<_, 3>(&mut a, 30, 20, 4.0);
+        assert!(a.iter().all(|&v| v == 200));
+        let mut b = vec![40000u16; 30 * 20 * 4];
+        gaussian_blur::<_, 4>(&mut b, 30, 20, 4.0);
+        assert!(b.iter().all(|&v| v == 40000));
+        let mut c = vec![0.25f32; 30 * 20];
+        gaussian_blur::<_, 1>(&mut c, 30, 20, 4.0);
+        assert!(c.iter().all(|&v| (v - 0.25).abs() < 1e-5));
+    }
+
+    #[test]
+    fn impulse() {
+        let (w, h) = (41, 31);
+        let mut a = vec![0f32; w * h];
+        a[15 * w + 20] = 1000.0;
+        gaussian_blur::<_, 1>(&mut a, w, h, 3.0);
+        let sum: f32 = a.iter().sum();
+        assert!((sum - 1000.0).abs() < 0.1);
+        // 中心最亮，上下左右对称
+        let at = |x: usize, y: usize| a[y * w + x];
+        assert!(a.iter().all(|&v| v <= at(20, 15)));
+        assert!((at(16, 15) - at(24, 15)).abs() < 1e-3);
+        assert!((at(20, 11) - at(20, 19)).abs() < 1e-3);
+        assert!((at(16, 15) - at(20, 11)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn banded() {
+        let (w, h) = (23, 97);
+        let src: Vec<u8> = (0..w * h * 3).map(|i| (i * 7 % 251) as u8).collect();
+        let mut full = src.clone();
+        gaussian_blur::<_, 3>(&mut full, w, h, 5.0);
+        // 条高小于 halo 时也须一致
+        for budget in [1, w * 3 * 2 * 30, w * 3 * 2 * 60] {
+            let mut tiled = src.clone();
+            gaussian_blur_banded::<_, 3>(&mut tiled, w, h, 5.0, Edge::Clamp, Some(budget));
+            assert_eq!(full, tiled, "{budget}");
+        }
+    }
+
+    #[test]
+    fn premultiplied() {
+        // 左半透明红色，右半不透明蓝色，交界处不应混入红色
+        let (w, h) = (20, 4);
+        let mut a: Vec<u8> = (0..w * h)
+            .flat_map(|i| {
+                if i % w < w / 2 {
+                    [255, 0, 0, 0]
+                } else {
+                    [0, 0, 255, 255]
+                }
+            })
+            .collect();
+        let mut straight = a.clone();
+        gaussian_blur_premultiplied::<_, 4>(&mut a, w, h, 2.0);
+        gaussian_blur::<_, 4>(&mut straight, w, h, 2.0);
+        for x in 0..w {
+            let p = &a[x * 4..x * 4 + 4];
+            if p[3] > 0 {
+                assert_eq!(&p[..3], &[0, 0, 255], "{x}");
+            }
+        }
+        // 直接模糊则红色渗入
+        assert!(straight.chunks(4).any(|p| p[3] > 0 && p[0] > 0));
+        // alpha 的模糊结果相同
+        let alpha = |v: &[u8]| v.chunks(4).map(|p| p[3] as i32).collect::<Vec<_>>();
+        let d = alpha(&a)
+            .iter()
+            .zip(alpha(&straight))
+            .map(|(x, y)| (x - y).abs())
+            .max();
+        assert!(d.unwrap() <= 1);
+    }
+
+    #[test]
+    fn edges() {
+        let n = 5;
+        let map = |e: Edge| (-3..8).map(|i| e.index(i, n)).collect::<Vec<_>>();
+        assert_eq!(map(Edge::Clamp), [0, 0, 0, 0, 1, 2, 3, 4, 4, 4, 4]);
+        assert_eq!(map(Edge::Mirror), [3, 2, 1, 0, 1, 2, 3, 4, 3, 2, 1]);
+        assert_eq!(map(Edge::Wrap), [2, 3, 4, 0, 1, 2, 3, 4, 0, 1, 2]);
+        assert_eq!(Edge::Mirror.index(-7, 1), 0);
+        // 分条与整幅结果一致
+        let (w, h) = (64, 8);
+        let src: Vec<u8> = (0..w * h).map(|i| (i * 13 % 241) as u8).collect();
+        for edge in [Edge::Mirror, Edge::Clamp, Edge::Wrap] {
+            let mut full = src.clone();
+            gaussian_blur_edge::<_, 1>(&mut full, w, h, 6.0, edge);
+            let mut tiled = src.clone();
+            gaussian_blur_banded::<_, 1>(&mut tiled, w, h, 6.0, edge, Some(w * 2));
+            assert_eq!(full, tiled, "{edge:?}");
+        }
+        // 边缘一列偏暗，延伸时它在窗口中重复多次，镜像时只计一次
+        let src: Vec<u8> = (0..w * h)
+            .map(|i| if i % w == 0 { 0 } else { 200 })
+            .collect();
+        let mut mirror = src.clone();
+        gaussian_blur_edge::<_, 1>(&mut mirror, w, h, 6.0, Edge::Mirror);
+        let mut clamp = src.clone();
+        gaussian_blur_edge::<_, 1>(&mut clamp, w, h, 6.0, Edge::Clamp);
+        assert!(mirror[0] > clamp[0] + 20, "{} {}", mirror[0], clamp[0]);
+    }
+
+    #[test]
+    fn rotate() {
+        let (w, h) = (37, 5);
+        let src: Vec<u16> = (0..(w * h * 2) as u16).collect();
+        let mut t = vec![0; src.len()];
+        let mut back = vec![0; src.len()];
+        transpose::<_, 2>(&src, &mut t, w, h);
+        assert_eq!(&t[2..4], &src[w * 2..w * 2 + 2]);
+        transpose::<_, 2>(&t, &mut back, h, w);
+        assert_eq!(src, back);
+    }
+
+    /// 24MP 照片按 sigma 50 模糊的耗时。计时受机器影响，默认不运行：
+    /// cargo test --release -p bmps blur::tests::speed -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn speed() {
+        let (w, h) = (6000, 4000);
+        let src: Vec<u8> = (0..w * h * 3).map(|i| (i * 7 % 251) as u8).collect();
+        for edge in [Edge::Clamp, Edge::Mirror] {
+            let mut a = src.clone();
+            let s = std::time::Instant::now();
+            gaussian_blur_edge::<_, 3>(&mut a, w, h, 50.0, edge);
+            let ms = s.elapsed().as_millis();
+            let threads = rayon::current_num_threads();
+            println!("{edge:?}: {ms}ms on {threads} threads");
+            // 单核约 1.1 秒；逐像素判断边界的旧实现 Clamp 约 2.0 秒、Mirror 约 2.6 秒
+            assert!(ms as usize * threads < 2000, "{edge:?} {ms}ms");
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use std::ops::Rem;
 
+use crate::blur::Edge;
+
 #[derive(Clone, Debug)]
 pub struct Config {
     pub source_file: String,
@@ -7,6 +9,416 @@ pub struct Config {
     pub font: Option<String>,
     pub size: Size,
     pub white_bg: bool,
+    // 标题文字，需同时指定 font
+    pub caption: Option<Caption>,
+    // 在照片一角印上拍摄日期
+    pub date_stamp: Option<DateStamp>,
+    // 中间缓冲区的内存上限(字节)，超出时分条渲染，结果与整幅渲染一致；
+    // 不含解码后的照片与输出画布；预算容不下模糊半径所需的上下各一段行时，
+    // 退化为逐行分条。None 为整幅渲染
+    pub memory_budget: Option<usize>,
+    // 在线性光下模糊背景、混合阴影与照片，边缘不发暗，阴影过渡更自然
+    pub linear_light: bool,
+    // 模糊背景的色调调整，默认不做调整
+    pub tone: Tone,
+    // 在背景上叠加颗粒噪点，打散大半径模糊在 8 位下的色带
+    pub grain: Option<Grain>,
+    // 背景的来源与取景
+    pub background: Background,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Background {
+    pub mode: BackgroundMode,
+    // 另用一张图片作背景，如纹理或品牌图。None 时取照片本身
+    pub image: Option<String>,
+    // 放大倍数，不小于 1
+    pub zoom: f32,
+    // 取景中心，背景源宽高的比例，取景框超出图像时靠边
+    pub focus_x: f32,
+    pub focus_y: f32,
+}
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            mode: BackgroundMode::Blur,
+            image: None,
+            zoom: 1.0,
+            focus_x: 0.5,
+            focus_y: 0.5,
+        }
+    }
+}
+impl Background {
+    /// 在 src_width x src_height 的背景源中，按 width x height 的宽高比、
+    /// 放大倍数与取景中心截取的区域 (x, y, w, h)。
+    /// 不放大且居中时返回 None，与直接 resize_to_fill 一致
+    pub(crate) fn frame(
+        &self,
+        src_width: u32,
+        src_height: u32,
+        width: u32,
+        height: u32,
+    ) -> Option<(u32, u32, u32, u32)> {
+        let zoom = self.zoom.max(1.0) as f64;
+        if zoom == 1.0 && self.focus_x == 0.5 && self.focus_y == 0.5 {
+            return None;
+        }
+        let (sw, sh) = (src_width as f64, src_height as f64);
+        let ratio = width as f64 / height as f64;
+        let (w, h) = if sw / sh > ratio {
+            (sh * ratio, sh)
+        } else {
+            (sw, sw / ratio)
+        };
+        let w = ((w / zoom).round() as u32).clamp(1, src_width);
+        let h = ((h / zoom).round() as u32).clamp(1, src_height);
+        let pos = |focus: f32, len: u32, src: u32| {
+            let x = (focus as f64 * src as f64 - len as f64 / 2.0).round();
+            x.clamp(0.0, (src - len) as f64) as u32
+        };
+        let x = pos(self.focus_x, w, src_width);
+        let y = pos(self.focus_y, h, src_height);
+        Some((x, y, w, h))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tone {
+    // 曝光补偿(档)，-1 亮度减半
+    pub exposure: f32,
+    // 饱和度倍数，0 为灰度
+    pub saturation: f32,
+    // 叠加的颜色，rgba，alpha 为叠加程度
+    pub tint: [u8; 4],
+    // 暗角强度 [0, 1]，1 时四角全黑
+    pub vignette: f32,
+}
+impl Default for Tone {
+    fn default() -> Self {
+        Tone {
+            exposure: 0.0,
+            saturation: 1.0,
+            tint: [0; 4],
+            vignette: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grain {
+    // 噪点幅度，以 8 位色阶计，1 约为一个色阶的抖动
+    pub strength: f32,
+    // 颗粒大小(像素)，1 为逐像素抖动，更大时为胶片颗粒质感
+    pub size: f32,
+    // 三个通道使用相同的噪点
+    pub monochrome: bool,
+    // 随机种子，相同种子得到相同的噪点
+    pub seed: u64,
+}
+impl Default for Grain {
+    fn default() -> Self {
+        Grain {
+            strength: 1.0,
+            size: 1.0,
+            monochrome: true,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DateStamp {
+    // YYYY YY MM DD hh mm ss 替换为拍摄时间，支持数字与 ' - : . / 空格
+    pub format: String,
+    pub corner: Corner,
+    // 字高，照片短边的比例
+    pub size: f64,
+    // 与照片边缘的距离，照片短边的比例
+    pub margin: f64,
+    // rgb
+    pub color: [u8; 3],
+}
+impl Default for DateStamp {
+    fn default() -> Self {
+        DateStamp {
+            format: "'YY MM DD".to_owned(),
+            corner: Corner::BottomRight,
+            size: 0.04,
+            margin: 0.05,
+            color: [255, 150, 40],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Clone, Debug)]
+pub struct Caption {
+    pub spans: Vec<Span>,
+    // 位于照片哪一侧的留白中
+    pub side: Side,
+    // 竖排：字自上而下，列自右向左
+    pub vertical: bool,
+    // 横排时为各行的水平对齐，竖排时为各列的竖直对齐
+    pub align: Align,
+    // 行距(竖排时为列距)，字号的倍数
+    pub line_spacing: f32,
+    // 根据背景亮度自动选择浅色或深色文字，忽略 span 的颜色(保留透明度)
+    pub auto_color: Option<AutoColor>,
+    // 文字特效，按顺序自下而上绘制于文字之下
+    pub effects: Vec<TextEffect>,
+}
+impl Default for Caption {
+    fn default() -> Self {
+        Caption {
+            spans: vec![],
+            side: Side::Bottom,
+            vertical: false,
+            align: Align::Center,
+            line_spacing: 1.5,
+            auto_color: None,
+            effects: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum TextEffect {
+    // 同 CSS text-shadow
+    Shadow {
+        offset_x: i32,
+        offset_y: i32,
+        blur_radius: u32,
+        color: [u8; 4],
+    },
+    // 描边宽度(像素数)
+    Outline {
+        width: u32,
+        color: [u8; 4],
+    },
+    // 外发光，先向外扩张 spread 像素再模糊 radius
+    Glow {
+        radius: u32,
+        spread: u32,
+        color: [u8; 4],
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct AutoColor {
+    // 最小对比度，WCAG AA 正文为 4.5，大号文字为 3
+    pub min_contrast: f64,
+    // 对比度不足时为文字加一层反色的淡阴影
+    pub shadow: bool,
+}
+impl Default for AutoColor {
+    fn default() -> Self {
+        AutoColor {
+            min_contrast: 4.5,
+            shadow: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub text: String,
+    // 字体文件路径，为 None 时使用 Config::font
+    pub font: Option<String>,
+    // 同 CSS font-weight，大于 500 时合成粗体，小于 400 时合成细体
+    pub weight: u16,
+    // 字号(像素数)
+    pub size: f32,
+    // rgba
+    pub color: [u8; 4],
+    // 字间距(像素数)
+    pub letter_spacing: f32,
+}
+impl Default for Span {
+    fn default() -> Self {
+        Span {
+            text: String::new(),
+            font: None,
+            weight: 400,
+            size: 48.0,
+            color: [255; 4],
+            letter_spacing: 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Start,
+    Center,
+    End,
+}
+
+// 长度：像素数，或相对画布宽高的比例
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Length {
+    Pixels(i32),
+    Ratio(f64),
+}
+
+impl Length {
+    /// 按画布的宽或高 total 换算为像素数
+    pub(crate) fn resolve(&self, total: u32) -> i64 {
+        match *self {
+            Length::Pixels(v) => v as i64,
+            Length::Ratio(r) => (r * total as f64).round() as i64,
+        }
+    }
+}
+
+// 照片四周的留白，左右按画布宽、上下按画布高换算
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Padding {
+    pub top: Length,
+    pub right: Length,
+    pub bottom: Length,
+    pub left: Length,
+}
+
+impl Padding {
+    /// 四边相同比例的留白，ratio 取值 [0, 0.5)
+    pub fn uniform(ratio: f64) -> Padding {
+        let r = Length::Ratio(ratio);
+        Padding {
+            top: r,
+            right: r,
+            bottom: r,
+            left: r,
+        }
+    }
+
+    /// width x height 画布中留白以内的区域 (x, y, w, h)
+    pub(crate) fn inner(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let side = |l: Length, total: u32| l.resolve(total).clamp(0, total as i64) as u32;
+        let (left, right) = (side(self.left, width), side(self.right, width));
+        let (top, bottom) = (side(self.top, height), side(self.bottom, height));
+        (
+            left,
+            top,
+            width.saturating_sub(left + right),
+            height.saturating_sub(top + bottom),
+        )
+    }
+
+    /// 边长 len 的照片加上两侧留白 a、b 所需的画布边长
+    fn fit(len: u32, a: Length, b: Length) -> f64 {
+        let pixels: f64 = [a, b]
+            .iter()
+            .map(|l| match *l {
+                Length::Pixels(v) => v.max(0) as f64,
+                Length::Ratio(_) => 0.0,
+            })
+            .sum();
+        // 两侧比例之和应小于 1
+        (len as f64 + pixels) / (1.0 - Padding::ratio(a, b)).max(0.01)
+    }
+
+    /// 两侧按比例留白的比例之和
+    fn ratio(a: Length, b: Length) -> f64 {
+        [a, b]
+            .iter()
+            .map(|l| match *l {
+                Length::Ratio(r) => r.max(0.0),
+                Length::Pixels(_) => 0.0,
+            })
+            .sum()
+    }
+}
+
+impl Default for Padding {
+    fn default() -> Self {
+        Padding::uniform(0.1)
+    }
+}
+
+// 背景的生成方式
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BackgroundMode {
+    // 模糊的照片副本，或 Background::image 指定的图片
+    #[default]
+    Blur,
+    // 将照片四边的像素向外拉伸到留白中，离照片越远越模糊。
+    // spread 为拖影的展开速度，距照片 d 像素处沿边取 ±spread * d 的均值。
+    // 总是取照片本身，忽略 image、zoom 与取景中心
+    Extend {
+        spread: f32,
+    },
+    // 马赛克，cell 为方格边长(像素)，不模糊
+    Mosaic {
+        cell: u32,
+    },
+    // 模糊后按亮度映射到暗、亮两种颜色之间，rgb。None 时取照片暗部、亮部的平均色
+    Duotone {
+        dark: Option<[u8; 3]>,
+        light: Option<[u8; 3]>,
+    },
+    // 毛玻璃：模糊后提高饱和度，叠加浅色与细噪点
+    Frosted {
+        // 饱和度倍数
+        saturation: f32,
+        // 噪点幅度，同 Grain::strength
+        noise: f32,
+        // rgba，alpha 为叠加程度
+        tint: [u8; 4],
+    },
+    // 程序生成的图案，颜色取自照片，同一 seed 在任意分辨率下图案相同
+    Procedural {
+        pattern: Pattern,
+        seed: u64,
+    },
+    // 透明画布，只保留圆角照片与阴影，输出 RGBA，便于放入其他版面
+    Transparent,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pattern {
+    // Mandelbrot 分形
+    #[default]
+    Fractal,
+    // 多层 Perlin 噪声
+    Noise,
+    // 网格渐变，若干控制点的颜色平滑过渡
+    Mesh,
+}
+
+// 背景模糊的风格，模糊半径均为 Size::blur_radius
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum BlurStyle {
+    #[default]
+    Gaussian,
+    // 圆盘模糊，模拟焦外光斑，highlight 越大亮部光斑越明显
+    Bokeh {
+        highlight: f32,
+    },
+    // 运动模糊，angle 为方向(度)，0 为水平，逆时针为正
+    Motion {
+        angle: f32,
+    },
+    // 放射模糊，中心为画布宽高的比例，离中心越远拖影越长
+    Zoom {
+        center_x: f32,
+        center_y: f32,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -21,9 +433,18 @@ pub struct Size {
     pub aspect_ratio: bool,
     // 背景图高斯模糊半径(像素数)
     pub blur_radius: u32,
+    // 背景模糊时图像边缘的处理方式
+    pub blur_edge: Edge,
+    pub blur_style: BlurStyle,
     // 圆角半径(像素数)
     pub round_radius: u32,
-    pub padding: f64,
+    pub padding: Padding,
+    // 照片在留白中的水平、竖直对齐方式
+    pub align_x: Align,
+    pub align_y: Align,
+    // 对齐后再偏移，正值向右、向下
+    pub offset_x: Length,
+    pub offset_y: Length,
     // 阴影模糊半径
     pub shadow: u32,
     // 阴影水平偏移量，同 CSS box-shadow
@@ -38,8 +459,14 @@ impl Default for Size {
             height: 1080,
             aspect_ratio: false,
             blur_radius: 50,
+            blur_edge: Edge::Mirror,
+            blur_style: BlurStyle::Gaussian,
             round_radius: 45,
-            padding: 0.1,
+            padding: Padding::uniform(0.1),
+            align_x: Align::Center,
+            align_y: Align::Center,
+            offset_x: Length::Pixels(0),
+            offset_y: Length::Pixels(0),
             shadow: 40,
             shadow_offset_x: 30,
             shadow_offset_y: 30,
@@ -67,16 +494,90 @@ impl Size {
         // 约分背景宽高比
         let bg_width = self.width / g;
         let bg_height = self.height / g;
-        // 约分原图宽高比
-        let g = calc_gcd(width, height);
-        let w = width / g;
-        let h = height / g;
-        if bg_width * h >= w * bg_height {
-            let res_h = (height as f64 / (1.0 - self.padding * 2.0)) as u32;
-            (bg_width * res_h / bg_height, res_h)
-        } else {
-            let res_w = (width as f64 / (1.0 - self.padding * 2.0)) as u32;
-            (res_w, bg_height * res_w / bg_width)
+        // 照片加上留白后至少需要的宽高，按背景宽高比向上取整补齐另一方向
+        let p = &self.padding;
+        let w = Padding::fit(width, p.left, p.right).ceil() as u64;
+        let h = Padding::fit(height, p.top, p.bottom).ceil() as u64;
+        let (bw, bh) = (bg_width as u64, bg_height as u64);
+        let by_height = bw * h >= w * bh;
+        let canvas = |side: u64| {
+            let (w, h) = if by_height {
+                ((bw * side).div_ceil(bh), side)
+            } else {
+                (side, (bh * side).div_ceil(bw))
+            };
+            (w as u32, h as u32)
+        };
+        let fits = |(w, h): (u32, u32)| {
+            let (_, _, iw, ih) = p.inner(w, h);
+            iw >= width && ih >= height
+        };
+        // 比例留白四舍五入到整像素，可能多占一两个像素，此时逐像素放大画布。
+        // 两侧比例之和不小于 1 时怎样都放不下，不再放大
+        let fixable =
+            Padding::ratio(p.left, p.right) < 1.0 && Padding::ratio(p.top, p.bottom) < 1.0;
+        let mut side = if by_height { h } else { w };
+        while fixable && !fits(canvas(side)) {
+            side += 1;
         }
+        canvas(side)
+    }
+
+    /// 照片在 width x height 画布中的左上角坐标。照片在留白以内的区域中对齐，
+    /// 偏移后限制在画布内，照片不会超出画布
+    pub(crate) fn position(&self, width: u32, height: u32, photo: (u32, u32)) -> (u32, u32) {
+        let (x, y, w, h) = self.padding.inner(width, height);
+        let axis = |total: u32, start: u32, inner: u32, len: u32, align: Align, offset: Length| {
+            let free = inner.saturating_sub(len);
+            let v = start
+                + match align {
+                    Align::Start => 0,
+                    Align::Center => free / 2,
+                    Align::End => free,
+                };
+            let max = total.saturating_sub(len) as i64;
+            (v as i64 + offset.resolve(total)).clamp(0, max) as u32
+        };
+        (
+            axis(width, x, w, photo.0, self.align_x, self.offset_x),
+            axis(height, y, h, photo.1, self.align_y, self.offset_y),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_length() {
+        // 比例按所在方向换算：左右按宽，上下按高
+        assert_eq!(Length::Ratio(0.1).resolve(1920), 192);
+        assert_eq!(Length::Ratio(0.1).resolve(1080), 108);
+        assert_eq!(Length::Ratio(-0.05).resolve(1080), -54);
+        let padding = Padding {
+            top: Length::Ratio(0.05),
+            right: Length::Ratio(0.1),
+            bottom: Length::Ratio(0.2),
+            left: Length::Pixels(40),
+        };
+        assert_eq!(padding.inner(1920, 1080), (40, 54, 1688, 810));
+        assert_eq!(
+            Padding::uniform(0.1).inner(1920, 1080),
+            (192, 108, 1536, 864)
+        );
+        // 宽高比模式：照片 1600x800，横向需 (1600 + 40) / 0.9，纵向需 800 / 0.75，
+        // 纵向更紧，取 1067，按 16:9 向上取整补齐宽度
+        let size = Size {
+            width: 16,
+            height: 9,
+            aspect_ratio: true,
+            padding,
+            ..Default::default()
+        };
+        let (w, h) = size.calc_bg(1600, 800);
+        assert_eq!((w, h), (1897, 1067));
+        let (_, _, iw, ih) = padding.inner(w, h);
+        assert!(iw >= 1600 && ih >= 800, "{iw} {ih}");
     }
 }
@@ -0,0 +1,122 @@
+//! 子像素位深。渲染流程对 u8、u16、f32 通用，按照片的位深选择，
+//! 16 位与浮点照片全程不降为 8 位
+use std::sync::OnceLock;
+
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive, Rgb, Rgba};
+
+use crate::blur::Sample;
+use crate::gamma::Gamma;
+
+pub trait Depth: Sample + Primitive + 'static {
+    /// 线性光下计算所用的位深。线性值暗部精度不足，8 位升为 16 位，16 位升为浮点
+    type Linear: Depth;
+    /// 此位深共享的 sRGB 转换表，首次使用时构建
+    fn gamma() -> &'static Gamma<Self>;
+    /// 8 位颜色分量换算到此位深
+    fn from_u8(v: u8) -> Self;
+    /// 整数类型向上取整，浮点类型原样保留
+    fn ceil(v: f64) -> Self;
+    // image 未导出 Rgb<T>: Pixel 所需的 Enlargeable，
+    // 泛型代码需自行约束 Rgb<S>: Pixel<Subpixel = S>
+    fn rgb(img: DynamicImage) -> ImageBuffer<Rgb<Self>, Vec<Self>>
+    where
+        Rgb<Self>: Pixel<Subpixel = Self>;
+    fn rgba(img: DynamicImage) -> ImageBuffer<Rgba<Self>, Vec<Self>>
+    where
+        Rgba<Self>: Pixel<Subpixel = Self>;
+    fn dynamic(img: ImageBuffer<Rgb<Self>, Vec<Self>>) -> DynamicImage
+    where
+        Rgb<Self>: Pixel<Subpixel = Self>;
+    fn dynamic_rgba(img: ImageBuffer<Rgba<Self>, Vec<Self>>) -> DynamicImage
+    where
+        Rgba<Self>: Pixel<Subpixel = Self>;
+}
+
+impl Depth for u8 {
+    type Linear = u16;
+    fn gamma() -> &'static Gamma<Self> {
+        static GAMMA: OnceLock<Gamma<u8>> = OnceLock::new();
+        GAMMA.get_or_init(Gamma::new)
+    }
+    fn from_u8(v: u8) -> Self {
+        v
+    }
+    fn ceil(v: f64) -> Self {
+        Self::from_f64(v.ceil())
+    }
+    fn rgb(img: DynamicImage) -> ImageBuffer<Rgb<Self>, Vec<Self>> {
+        img.into_rgb8()
+    }
+    fn rgba(img: DynamicImage) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        img.into_rgba8()
+    }
+    fn dynamic(img: ImageBuffer<Rgb<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgb8(img)
+    }
+    fn dynamic_rgba(img: ImageBuffer<Rgba<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgba8(img)
+    }
+}
+
+impl Depth for u16 {
+    type Linear = f32;
+    fn gamma() -> &'static Gamma<Self> {
+        static GAMMA: OnceLock<Gamma<u16>> = OnceLock::new();
+        GAMMA.get_or_init(Gamma::new)
+    }
+    fn from_u8(v: u8) -> Self {
+        v as u16 * 257
+    }
+    fn ceil(v: f64) -> Self {
+        Self::from_f64(v.ceil())
+    }
+    fn rgb(img: DynamicImage) -> ImageBuffer<Rgb<Self>, Vec<Self>> {
+        img.into_rgb16()
+    }
+    fn rgba(img: DynamicImage) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        img.into_rgba16()
+    }
+    fn dynamic(img: ImageBuffer<Rgb<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgb16(img)
+    }
+    fn dynamic_rgba(img: ImageBuffer<Rgba<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgba16(img)
+    }
+}
+
+impl Depth for f32 {
+    type Linear = f32;
+    fn gamma() -> &'static Gamma<Self> {
+        static GAMMA: OnceLock<Gamma<f32>> = OnceLock::new();
+        GAMMA.get_or_init(Gamma::new)
+    }
+    fn from_u8(v: u8) -> Self {
+        v as f32 / 255.0
+    }
+    fn ceil(v: f64) -> Self {
+        v as f32
+    }
+    fn rgb(img: DynamicImage) -> ImageBuffer<Rgb<Self>, Vec<Self>> {
+        img.into_rgb32f()
+    }
+    fn rgba(img: DynamicImage) -> ImageBuffer<Rgba<Self>, Vec<Self>> {
+        img.into_rgba32f()
+    }
+    fn dynamic(img: ImageBuffer<Rgb<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgb32F(img)
+    }
+    fn dynamic_rgba(img: ImageBuffer<Rgba<Self>, Vec<Self>>) -> DynamicImage {
+        DynamicImage::ImageRgba32F(img)
+    }
+}
+
+/// 按取值范围换算到另一位深，位深相同时不变
+pub fn convert<S: Depth, T: Depth>(v: S) -> T {
+    let scale = T::DEFAULT_MAX_VALUE.into_f64() / S::DEFAULT_MAX_VALUE.into_f64();
+    T::from_f64(v.into_f64() * scale)
+}
+
+/// 按位深换算 rgba 颜色
+pub fn color<S: Depth>(rgba: [u8; 4]) -> Rgba<S> {
+    Rgba(rgba.map(S::from_u8))
+}
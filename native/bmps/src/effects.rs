@@ -1,2 +1,9 @@
+pub mod date_stamp;
+pub mod extend;
+pub mod glow;
+pub mod grain;
+pub mod outline;
 pub mod round;
 pub mod shadow;
+pub mod stylize;
+pub mod tone;
@@ -0,0 +1,186 @@
+//! 胶片相机风格的橙色日期印记：七段数码管字形，带轻微的晕染和光晕，
+//! 字形自行绘制，无需安装字体。
+use image::{GenericImage, Rgba, RgbaImage};
+
+use super::glow;
+use crate::config::{Corner, DateStamp};
+
+// 字形坐标以字高为单位，字宽 W，笔画粗 T
+const W: f32 = 0.55;
+const T: f32 = 0.13;
+// 笔画两端留出的缝隙
+const GAP: f32 = T * 0.6;
+// 斜体，顶部相对底部右移的比例
+const SLANT: f32 = 0.12;
+
+type Segment = ((f32, f32), (f32, f32));
+
+// 七段的端点：a 上、b 右上、c 右下、d 下、e 左下、f 左上、g 中
+fn seven(code: &str) -> Vec<Segment> {
+    let i = T / 2.0;
+    let tl = (i, i);
+    let tr = (W - i, i);
+    let ml = (i, 0.5);
+    let mr = (W - i, 0.5);
+    let bl = (i, 1.0 - i);
+    let br = (W - i, 1.0 - i);
+    code.chars()
+        .filter_map(|s| match s {
+            'a' => Some((tl, tr)),
+            'b' => Some((tr, mr)),
+            'c' => Some((mr, br)),
+            'd' => Some((bl, br)),
+            'e' => Some((ml, bl)),
+            'f' => Some((tl, ml)),
+            'g' => Some((ml, mr)),
+            _ => None,
+        })
+        .map(|(p0, p1)| shorten(p0, p1, GAP))
+        .collect()
+}
+
+fn shorten(p0: (f32, f32), p1: (f32, f32), gap: f32) -> Segment {
+    let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let len = (dx * dx + dy * dy).sqrt();
+    let (ux, uy) = (dx / len * gap, dy / len * gap);
+    ((p0.0 + ux, p0.1 + uy), (p1.0 - ux, p1.1 - uy))
+}
+
+/// 字形的笔画及步进宽度，不支持的字符按空格处理
+fn glyph(c: char) -> (Vec<Segment>, f32) {
+    let advance = W + T * 2.0;
+    let digit = |code: &str| (seven(code), advance);
+    match c {
+        '0' => digit("abcdef"),
+        '1' => digit("bc"),
+        '2' => digit("abged"),
+        '3' => digit("abgcd"),
+        '4' => digit("fgbc"),
+        '5' => digit("afgcd"),
+        '6' => digit("afgedc"),
+        '7' => digit("abc"),
+        '8' => digit("abcdefg"),
+        '9' => digit("abcdfg"),
+        '-' => digit("g"),
+        '\'' => (vec![((0.2, T / 2.0), (0.12, 0.25))], 0.35),
+        '.' => (vec![((0.1, 1.0 - T / 2.0), (0.1, 1.0 - T / 2.0))], 0.3),
+        ':' => (
+            vec![((0.1, 0.3), (0.1, 0.3)), ((0.1, 0.75), (0.1, 0.75))],
+            0.3,
+        ),
+        '/' => (vec![((W - T, T / 2.0), (T, 1.0 - T / 2.0))], advance),
+        _ => (vec![], advance * 0.6),
+    }
+}
+
+fn dist_to_segment(p: (f32, f32), s: &Segment) -> f32 {
+    let ((x0, y0), (x1, y1)) = *s;
+    let (dx, dy) = (x1 - x0, y1 - y0);
+    let len2 = dx * dx + dy * dy;
+    let t = if len2 == 0.0 {
+        0.0
+    } else {
+        (((p.0 - x0) * dx + (p.1 - y0) * dy) / len2).clamp(0.0, 1.0)
+    };
+    let (cx, cy) = (x0 + t * dx, y0 + t * dy);
+    ((p.0 - cx).powi(2) + (p.1 - cy).powi(2)).sqrt()
+}
+
+/// 按日期格式替换 YYYY YY MM DD hh mm ss，其余字符原样保留
+pub fn format(dt: &exif::DateTime, format: &str) -> String {
+    format
+        .replace("YYYY", format!("{:04}", dt.year).as_str())
+        .replace("YY", format!("{:02}", dt.year % 100).as_str())
+        .replace("MM", format!("{:02}", dt.month).as_str())
+        .replace("DD", format!("{:02}", dt.day).as_str())
+        .replace("hh", format!("{:02}", dt.hour).as_str())
+        .replace("mm", format!("{:02}", dt.minute).as_str())
+        .replace("ss", format!("{:02}", dt.second).as_str())
+}
+
+/// 以 height 像素的字高绘制数码管文字，返回透明背景的图层
+pub fn render(text: &str, height: f32, color: [u8; 3]) -> RgbaImage {
+    let mut segments = vec![];
+    let mut pen = 0.0;
+    for c in text.chars() {
+        let (segs, advance) = glyph(c);
+        for ((x0, y0), (x1, y1)) in segs {
+            segments.push(((x0 + pen, y0), (x1 + pen, y1)));
+        }
+        pen += advance;
+    }
+    let width = ((pen + SLANT) * height).ceil() as u32;
+    let mut layer = RgbaImage::from_pixel(
+        width.max(1),
+        height.ceil() as u32,
+        Rgba([color[0], color[1], color[2], 0]),
+    );
+    let half = T / 2.0;
+    layer.enumerate_pixels_mut().for_each(|(x, y, p)| {
+        let fy = (y as f32 + 0.5) / height;
+        // 逆斜体变换回直立坐标
+        let fx = (x as f32 + 0.5) / height - (1.0 - fy) * SLANT;
+        let d = segments
+            .iter()
+            .map(|s| dist_to_segment((fx, fy), s))
+            .fold(f32::MAX, f32::min);
+        let cov = (0.5 - (d - half) * height).clamp(0.0, 1.0);
+        p.0[3] = (cov * 255.0).round() as u8;
+    });
+    layer
+}
+
+/// 在照片一角印上日期
+pub fn apply<I: GenericImage<Pixel = Rgba<u8>>>(img: &mut I, stamp: &DateStamp, text: &str) {
+    let (w, h) = img.dimensions();
+    let short = w.min(h) as f64;
+    let height = (short * stamp.size).max(8.0) as f32;
+    let core = render(text, height, stamp.color);
+    // 晕染：紧贴笔画的偏红光边；光晕：更大范围的橙色辉光
+    let bleed = glow::Builder::new()
+        .radius((height / 16.0).ceil() as u32)
+        .color([255, 80, 0, 200])
+        .build();
+    let halo = glow::Builder::new()
+        .radius((height / 5.0).ceil() as u32)
+        .color([stamp.color[0], stamp.color[1], stamp.color[2], 110])
+        .build();
+    let margin = (short * stamp.margin) as i32;
+    let x = match stamp.corner {
+        Corner::TopLeft | Corner::BottomLeft => margin,
+        Corner::TopRight | Corner::BottomRight => w as i32 - margin - core.width() as i32,
+    };
+    let y = match stamp.corner {
+        Corner::TopLeft | Corner::TopRight => margin,
+        Corner::BottomLeft | Corner::BottomRight => h as i32 - margin - core.height() as i32,
+    };
+    for g in [halo, bleed] {
+        let (layer, dx, dy) = g.gen_bg(&core);
+        crate::overlay(img, &layer, x - dx as i32, y - dy as i32);
+    }
+    crate::overlay(img, &core, x, y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn date_format() {
+        let dt = exif::DateTime::from_ascii(b"2023:07:09 08:05:03").unwrap();
+        assert_eq!(format(&dt, "'YY MM DD"), "'23 07 09");
+        assert_eq!(format(&dt, "YYYY-MM-DD hh:mm:ss"), "2023-07-09 08:05:03");
+    }
+
+    #[test]
+    fn segments() {
+        // 8 点亮全部七段，1 只有右侧两段
+        let eight = render("8", 40.0, [255, 140, 30]);
+        let one = render("1", 40.0, [255, 140, 30]);
+        let ink = |l: &RgbaImage| l.pixels().filter(|p| p[3] > 128).count();
+        assert!(ink(&eight) > ink(&one) * 3);
+        // 中横杠
+        assert_eq!(eight.get_pixel(13, 20)[3], 255);
+        assert_eq!(one.get_pixel(13, 20)[3], 0);
+    }
+}
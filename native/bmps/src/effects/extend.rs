@@ -0,0 +1,158 @@
+//! 边缘延伸背景：将照片四边的像素向外拉伸到留白中，离照片越远拖影越宽、越模糊，
+//! 照片像是延续到了画框里。留白不均匀(如 aspect_ratio 模式)时各边分别延伸
+use image::{DynamicImage, GenericImageView, ImageBuffer, Pixel, Rgb};
+
+use crate::depth::Depth;
+
+/// 照片一条边上像素的前缀和，每个位置取向内 depth 个像素的均值
+struct Edge {
+    prefix: Vec<[f64; 3]>,
+}
+
+impl Edge {
+    /// 由照片边缘的一条像素带生成，vertical 为 true 时沿 y 方向
+    fn new<S: Depth>(strip: ImageBuffer<Rgb<S>, Vec<S>>, vertical: bool) -> Edge
+    where
+        Rgb<S>: Pixel<Subpixel = S>,
+    {
+        let (w, h) = strip.dimensions();
+        let (len, depth) = if vertical { (h, w) } else { (w, h) };
+        let mut prefix = vec![[0f64; 3]; len as usize + 1];
+        for i in 0..len {
+            let mut sum = prefix[i as usize];
+            for j in 0..depth {
+                let (x, y) = if vertical { (j, i) } else { (i, j) };
+                let p = strip.get_pixel(x, y);
+                for c in 0..3 {
+                    sum[c] += p[c].into_f64() / depth as f64;
+                }
+            }
+            prefix[i as usize + 1] = sum;
+        }
+        Edge { prefix }
+    }
+
+    /// 以 center 为中心、半宽 half 的窗口内的均值，窗口超出边缘时截断
+    fn mean(&self, center: f32, half: f32) -> [f64; 3] {
+        let last = self.prefix.len() as f32 - 2.0;
+        let a = (center - half).round().clamp(0.0, last) as usize;
+        let b = (center + half).round().clamp(0.0, last) as usize;
+        let n = (b - a + 1) as f64;
+        let (lo, hi) = (self.prefix[a], self.prefix[b + 1]);
+        [0, 1, 2].map(|c| (hi[c] - lo[c]) / n)
+    }
+}
+
+/// 生成 width x height 的背景，照片左上角位于 (x, y)
+pub fn apply<S: Depth>(
+    photo: &DynamicImage,
+    width: u32,
+    height: u32,
+    x: u32,
+    y: u32,
+    spread: f32,
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let (pw, ph) = photo.dimensions();
+    let mut bg = ImageBuffer::new(width, height);
+    if pw == 0 || ph == 0 {
+        return bg;
+    }
+    // 取向内几个像素的均值，减弱噪点拉出的细线
+    let depth = (1 + pw.min(ph) / 200).min(pw).min(ph);
+    let strip = |x, y, w, h| S::rgb(photo.crop_imm(x, y, w, h));
+    let left = Edge::new(strip(0, 0, depth, ph), true);
+    let right = Edge::new(strip(pw - depth, 0, depth, ph), true);
+    let top = Edge::new(strip(0, 0, pw, depth), false);
+    let bottom = Edge::new(strip(0, ph - depth, pw, depth), false);
+    let spread = spread.max(0.0);
+    // 到照片的距离，照片内为 0
+    let dist = |v: i64, len: u32| -> f32 {
+        if v < 0 {
+            -v as f32
+        } else {
+            (v - len as i64 + 1).max(0) as f32
+        }
+    };
+    crate::par_rows(&mut bg, |cy, row| {
+        let py = cy as i64 - y as i64;
+        let dy = dist(py, ph);
+        let side_y = if py < ph as i64 / 2 { &top } else { &bottom };
+        let fy = py.clamp(0, ph as i64 - 1) as f32;
+        for (cx, p) in row.chunks_exact_mut(3).enumerate() {
+            let px = cx as i64 - x as i64;
+            let dx = dist(px, pw);
+            let side_x = if px < pw as i64 / 2 { &left } else { &right };
+            let fx = px.clamp(0, pw as i64 - 1) as f32;
+            let v = if dx == 0.0 && dy == 0.0 {
+                // 照片下方(圆角、透明区域)取最近的一条边
+                let to_x = px.min(pw as i64 - 1 - px);
+                let to_y = py.min(ph as i64 - 1 - py);
+                if to_x <= to_y {
+                    side_x.mean(fy, 0.0)
+                } else {
+                    side_y.mean(fx, 0.0)
+                }
+            } else if dy == 0.0 {
+                side_x.mean(fy, spread * dx)
+            } else if dx == 0.0 {
+                side_y.mean(fx, spread * dy)
+            } else {
+                // 四角按方向混合相邻两边，与两侧的区域连续
+                let a = side_x.mean(fy, spread * dx);
+                let b = side_y.mean(fx, spread * dy);
+                let w = (dy / (dx + dy)) as f64;
+                [0, 1, 2].map(|c| a[c] * (1.0 - w) + b[c] * w)
+            };
+            for c in 0..3 {
+                p[c] = S::from_f64(v[c]);
+            }
+        }
+    });
+    bg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn extend() {
+        // 左半红右半蓝的照片，放在偏左上的位置
+        let photo = RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let photo = DynamicImage::ImageRgb8(photo);
+        let bg: RgbImage = apply(&photo, 100, 60, 10, 5, 1.0);
+        // 左右两侧沿用边缘颜色
+        assert_eq!(bg.get_pixel(0, 15).0, [255, 0, 0]);
+        assert_eq!(bg.get_pixel(99, 15).0, [0, 0, 255]);
+        // 上下两侧在拖影中逐渐混合
+        assert_eq!(bg.get_pixel(15, 4).0, [255, 0, 0]);
+        let far = bg.get_pixel(30, 59);
+        assert!(far[0] > 0 && far[2] > 0, "{far:?}");
+        // 四角连续
+        let (a, b) = (bg.get_pixel(9, 0), bg.get_pixel(10, 0));
+        assert!(a
+            .0
+            .iter()
+            .zip(b.0.iter())
+            .all(|(a, b)| a.abs_diff(*b) <= 16));
+        // 照片内取自身边缘
+        assert_eq!(bg.get_pixel(12, 15).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn flat() {
+        let photo = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(30, 30, Rgb([9000u16; 3])));
+        let bg: ImageBuffer<Rgb<u16>, _> = apply(&photo, 90, 50, 30, 0, 2.0);
+        assert!(bg.pixels().all(|p| p.0 == [9000; 3]));
+    }
+}
@@ -0,0 +1,77 @@
+use image::{GenericImageView, Pixel, Rgba, RgbaImage};
+
+use super::{outline, shadow};
+
+// 外发光：不透明区域先扩张 spread 像素，再以 radius 模糊，不偏移
+#[derive(Debug, Clone)]
+pub struct Glow {
+    radius: u32,
+    spread: u32,
+    // rgba
+    color: [u8; 4],
+}
+
+impl Default for Glow {
+    fn default() -> Self {
+        Glow {
+            radius: 8,
+            spread: 0,
+            color: [255, 255, 255, 200],
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Builder {
+    inner: Glow,
+}
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            inner: Default::default(),
+        }
+    }
+    pub fn radius(mut self, r: u32) -> Builder {
+        self.inner.radius = r;
+        self
+    }
+    pub fn spread(mut self, s: u32) -> Builder {
+        self.inner.spread = s;
+        self
+    }
+    pub fn color(mut self, rgba: [u8; 4]) -> Builder {
+        self.inner.color = rgba;
+        self
+    }
+    pub fn build(self) -> Glow {
+        self.inner
+    }
+}
+impl Glow {
+    /// 生成光晕图层，以及原图偏移量（ (0,0)在此图层的位置）
+    pub fn gen_bg<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+        let mut opaque = self.color;
+        opaque[3] = 255;
+        let (spread, sx, sy) = outline::Builder::new()
+            .width(self.spread)
+            .color(opaque)
+            .build()
+            .gen_bg(img);
+        let (bg, dx, dy) = shadow::Builder::new()
+            .offset(0, 0)
+            .blur_radius(self.radius)
+            .color(self.color)
+            .build()
+            .gen_bg(&spread);
+        (bg, dx + sx, dy + sy)
+    }
+
+    /// 生成带光晕的结果，以及原图偏移量（ (0,0)在此结果图中的位置）
+    pub fn apply<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+        let (mut bg, dx, dy) = self.gen_bg(img);
+        img.pixels().for_each(|(x, y, p)| {
+            bg.get_pixel_mut(x + dx, y + dy).blend(&p);
+        });
+        (bg, dx, dy)
+    }
+}
@@ -0,0 +1,124 @@
+//! 颗粒噪点：逐像素抖动打散色带，或放大颗粒得到胶片质感。
+//! 噪点由种子与坐标哈希得到，与线程划分无关，可复现
+use image::{ImageBuffer, Pixel, Rgb};
+
+use crate::config::Grain;
+use crate::depth::Depth;
+
+/// 在 RGB 图像上原地叠加噪点
+pub fn apply<S: Depth>(img: &mut ImageBuffer<Rgb<S>, Vec<S>>, grain: &Grain)
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    if grain.strength <= 0.0 {
+        return;
+    }
+    let max = S::DEFAULT_MAX_VALUE.into_f64() as f32;
+    let amp = grain.strength / 255.0 * max;
+    let size = grain.size.max(1.0);
+    crate::par_rows(img, |y, row| {
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            for (c, v) in p.iter_mut().enumerate() {
+                let c = if grain.monochrome { 0 } else { c as u64 };
+                let n = noise(grain.seed, c, x as f32, y as f32, size);
+                *v = S::from_f64((v.into_f64() as f32 + n * amp) as f64);
+            }
+        }
+    });
+}
+
+/// 坐标 (x, y) 处的噪点，取值 (-1, 1)，三角分布。
+/// size 大于 1 时在间距为 size 的格点间平滑插值，并保持方差不变
+fn noise(seed: u64, c: u64, x: f32, y: f32, size: f32) -> f32 {
+    if size <= 1.0 {
+        return tri(seed, c, x as i64, y as i64);
+    }
+    let (gx, gy) = (x / size, y / size);
+    let (x0, y0) = (gx.floor(), gy.floor());
+    let smooth = |t: f32| t * t * (3.0 - 2.0 * t);
+    let (fx, fy) = (smooth(gx - x0), smooth(gy - y0));
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let w = [
+        (1.0 - fx) * (1.0 - fy),
+        fx * (1.0 - fy),
+        (1.0 - fx) * fy,
+        fx * fy,
+    ];
+    let t = [
+        tri(seed, c, x0, y0),
+        tri(seed, c, x0 + 1, y0),
+        tri(seed, c, x0, y0 + 1),
+        tri(seed, c, x0 + 1, y0 + 1),
+    ];
+    let sum: f32 = w.iter().zip(t.iter()).map(|(w, t)| w * t).sum();
+    let norm = w.iter().map(|w| w * w).sum::<f32>().sqrt();
+    (sum / norm).clamp(-1.0, 1.0)
+}
+
+/// 两个均匀分布之和减一，得到 (-1, 1) 的三角分布
+fn tri(seed: u64, c: u64, x: i64, y: i64) -> f32 {
+    let h = hash(seed ^ hash(c ^ hash(x as u64 ^ hash(y as u64))));
+    let unit = |v: u64| (v & 0xFF_FFFF) as f32 / (1 << 24) as f32;
+    unit(h) + unit(h >> 32) - 1.0
+}
+
+// splitmix64
+pub(crate) fn hash(v: u64) -> u64 {
+    let mut z = v.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn gray() -> RgbImage {
+        RgbImage::from_pixel(64, 64, Rgb([128; 3]))
+    }
+
+    #[test]
+    fn seed() {
+        let grain = Grain {
+            strength: 3.0,
+            ..Default::default()
+        };
+        let (mut a, mut b, mut c) = (gray(), gray(), gray());
+        apply(&mut a, &grain);
+        apply(&mut b, &grain);
+        apply(&mut c, &Grain { seed: 7, ..grain });
+        assert!(a == b);
+        assert!(a != c);
+        // 均值不变，幅度不超过 strength
+        let sum: u32 = a.pixels().map(|p| p[0] as u32).sum();
+        assert!((sum as f32 / (64.0 * 64.0) - 128.0).abs() < 0.2);
+        assert!(a.pixels().all(|p| p[0].abs_diff(128) <= 3));
+        assert!(a.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+        assert!(a.pixels().any(|p| p[0] != 128));
+    }
+
+    #[test]
+    fn size() {
+        // 大颗粒的相邻像素更接近
+        let diff = |size: f32| {
+            let mut img = RgbImage::from_pixel(64, 64, Rgb([128; 3]));
+            let grain = Grain {
+                strength: 20.0,
+                size,
+                monochrome: false,
+                seed: 1,
+            };
+            apply(&mut img, &grain);
+            let d: u32 = img
+                .pixels()
+                .zip(img.pixels().skip(1))
+                .map(|(a, b)| a[0].abs_diff(b[0]) as u32)
+                .sum();
+            assert!(img.pixels().any(|p| p[0] != p[1]));
+            d
+        };
+        assert!(diff(8.0) * 3 < diff(1.0));
+    }
+}
@@ -0,0 +1,120 @@
+use image::{GenericImageView, Pixel, Rgba, RgbaImage};
+
+// 描边：不透明区域向外扩张 width 像素，边缘抗锯齿
+#[derive(Debug, Clone)]
+pub struct Outline {
+    width: u32,
+    // rgba
+    color: [u8; 4],
+}
+
+impl Default for Outline {
+    fn default() -> Self {
+        Outline {
+            width: 2,
+            color: [0, 0, 0, 255],
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct Builder {
+    inner: Outline,
+}
+impl Builder {
+    pub fn new() -> Builder {
+        Builder {
+            inner: Default::default(),
+        }
+    }
+    pub fn width(mut self, w: u32) -> Builder {
+        self.inner.width = w;
+        self
+    }
+    pub fn color(mut self, rgba: [u8; 4]) -> Builder {
+        self.inner.color = rgba;
+        self
+    }
+    pub fn build(self) -> Outline {
+        self.inner
+    }
+}
+impl Outline {
+    /// 生成描边图层，以及原图偏移量（ (0,0)在此图层的位置）
+    pub fn gen_bg<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+        let r = self.width;
+        let (w, h) = img.dimensions();
+        let mut b = RgbaImage::from_pixel(
+            w + r * 2,
+            h + r * 2,
+            Rgba({
+                let mut p = self.color;
+                p[3] = 0;
+                p
+            }),
+        );
+        if self.color[3] == 0 {
+            return (b, r, r);
+        }
+        let alpha: Vec<u8> = img.pixels().map(|(_, _, p)| p[3]).collect();
+        // 圆盘内各偏移的覆盖率，距离 r 以内为 1，r+1 处降为 0
+        let r = r as i32;
+        let mut disc = vec![];
+        for dy in -r - 1..=r + 1 {
+            for dx in -r - 1..=r + 1 {
+                let d = ((dx * dx + dy * dy) as f32).sqrt();
+                let cov = (r as f32 + 1.0 - d).clamp(0.0, 1.0);
+                if cov > 0.0 {
+                    disc.push((dx, dy, cov));
+                }
+            }
+        }
+        let scale = self.color[3] as f32 / 255.0;
+        crate::par_rows(&mut b, |y, row| {
+            for (x, p) in row.chunks_exact_mut(4).enumerate() {
+                let mut a: f32 = 0.0;
+                for &(dx, dy, cov) in disc.iter() {
+                    let sx = x as i32 - r + dx;
+                    let sy = y as i32 - r + dy;
+                    if sx < 0 || sy < 0 || sx as u32 >= w || sy as u32 >= h {
+                        continue;
+                    }
+                    a = a.max(alpha[(sy as u32 * w + sx as u32) as usize] as f32 * cov);
+                }
+                p[3] = (a * scale).round() as u8;
+            }
+        });
+        (b, r as u32, r as u32)
+    }
+
+    /// 生成描边后的结果，以及原图偏移量（ (0,0)在此结果图中的位置）
+    pub fn apply<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+        let (mut bg, dx, dy) = self.gen_bg(img);
+        img.pixels().for_each(|(x, y, p)| {
+            bg.get_pixel_mut(x + dx, y + dy).blend(&p);
+        });
+        (bg, dx, dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dot() {
+        let mut img = RgbaImage::new(5, 5);
+        img.put_pixel(2, 2, Rgba([255; 4]));
+        let (bg, dx, dy) = Builder::new()
+            .width(2)
+            .color([255, 0, 0, 255])
+            .build()
+            .gen_bg(&img);
+        assert_eq!((bg.width(), bg.height(), dx, dy), (9, 9, 2, 2));
+        let c = (2 + dx, 2 + dy);
+        assert_eq!(bg.get_pixel(c.0, c.1).0, [255, 0, 0, 255]);
+        assert_eq!(bg.get_pixel(c.0 + 2, c.1)[3], 255);
+        assert_eq!(bg.get_pixel(c.0 + 3, c.1)[3], 0);
+        assert!(bg.get_pixel(c.0 + 2, c.1 + 2)[3] < 255);
+    }
+}
@@ -1,5 +1,5 @@
-pub struct Rounded<'a> {
-    origin: &'a image::DynamicImage,
+pub struct Rounded<'a, I = image::DynamicImage> {
+    origin: &'a I,
     checker: Checker,
 }
 pub struct Checker {
@@ -7,95 +7,53 @@ pub struct Checker {
     pub height: u32,
     pub radius: u32,
 }
-#[cfg(test)]
-mod test {
-    use image::GenericImageView;
-    use image::Pixel;
-
-    use super::apply;
-    use super::Checker;
-    use super::Rounded;
-
-    #[test]
-    fn round() {
-        let img = image::open("./hello.jpg").unwrap();
-        let mut img = image::DynamicImage::ImageRgba8(
-            img.resize_to_fill(1920, 1080, image::imageops::FilterType::Nearest)
-                .to_rgba8(),
-        );
-        let mut tmp = image::RgbaImage::from_pixel(img.width(), img.height(), image::Rgba([0; 4]));
-        let r = Rounded::new(&img, 40);
-        tmp.enumerate_pixels_mut().for_each(|(x, y, p)| {
-            let v = r.get_pixel(x, y);
-            p.blend(&v);
-        });
-        apply(&mut img, 40);
-        img.save("output_round.png").unwrap();
-        tmp.save("output_round1.png").unwrap();
-    }
-    #[test]
-    fn checker() {
-        let c = Checker {
-            width: 100,
-            height: 100,
-            radius: 30,
-        };
-        assert!(!c.contains(0, 0));
-        assert!(!c.contains(8, 8));
-        assert!(!c.contains(99, 99));
-        assert!(!c.contains(93, 93));
-        assert!(!c.contains(99, 0));
-        assert!(!c.contains(0, 99));
-        assert!(c.contains(50, 50));
-    }
-}
 pub fn apply(img: &mut image::DynamicImage, radius: u32) {
     let checker = Checker {
         width: img.width(),
         height: img.height(),
         radius,
     };
-    let walk = |mut cb: Box<dyn FnMut(u32, u32)>| {
-        for x in 0..checker.width {
-            for y in 0..checker.height {
-                if checker.contains(x, y) {
-                    break;
-                }
-                cb(x, y); // 左上
-                cb(checker.width - x - 1, y); // 右上
-                cb(x, checker.height - y - 1); // 左下
-                cb(checker.width - x - 1, checker.height - y - 1); // 右下
-            }
-        }
-    };
     match img {
-        image::DynamicImage::ImageRgb8(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [255; 3];
-        })),
-        image::DynamicImage::ImageRgba8(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [0; 4];
-        })),
-        image::DynamicImage::ImageRgb16(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [255; 3];
-        })),
-        image::DynamicImage::ImageRgba16(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [0; 4];
-        })),
-        image::DynamicImage::ImageRgb32F(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [1.0; 3];
-        })),
-        image::DynamicImage::ImageRgba32F(v) => walk(Box::new(|x, y| {
-            v.get_pixel_mut(x, y).0 = [0.0; 4];
-        })),
-        image::DynamicImage::ImageLuma8(_) => {}   // TODO
-        image::DynamicImage::ImageLumaA8(_) => {}  // TODO
-        image::DynamicImage::ImageLuma16(_) => {}  // TODO
-        image::DynamicImage::ImageLumaA16(_) => {} // TODO
+        image::DynamicImage::ImageRgb8(v) => clear(v, &checker, [255; 3]),
+        image::DynamicImage::ImageRgba8(v) => clear(v, &checker, [0; 4]),
+        image::DynamicImage::ImageRgb16(v) => clear(v, &checker, [u16::MAX; 3]),
+        image::DynamicImage::ImageRgba16(v) => clear(v, &checker, [0; 4]),
+        image::DynamicImage::ImageRgb32F(v) => clear(v, &checker, [1.0; 3]),
+        image::DynamicImage::ImageRgba32F(v) => clear(v, &checker, [0.0; 4]),
+        image::DynamicImage::ImageLuma8(v) => clear(v, &checker, [255]),
+        image::DynamicImage::ImageLumaA8(v) => clear(v, &checker, [0; 2]),
+        image::DynamicImage::ImageLuma16(v) => clear(v, &checker, [u16::MAX]),
+        image::DynamicImage::ImageLumaA16(v) => clear(v, &checker, [0; 2]),
         _ => {}
     }
 }
-impl<'a> Rounded<'a> {
-    pub fn new(img: &image::DynamicImage, radius: u32) -> Rounded<'_> {
+
+/// 按行并行地将四角圆弧外的像素填充为 fill
+fn clear<P, const N: usize>(
+    img: &mut image::ImageBuffer<P, Vec<P::Subpixel>>,
+    checker: &Checker,
+    fill: [P::Subpixel; N],
+) where
+    P: image::Pixel,
+    P::Subpixel: Send + Sync,
+{
+    let (w, h) = (checker.width, checker.height);
+    crate::par_rows(img, |y, row| {
+        // 上下两半对称，均按左上角判断
+        let y = y.min(h - y - 1);
+        for x in 0..w {
+            if checker.contains(x, y) {
+                break;
+            }
+            let l = x as usize * N;
+            let r = (w - x - 1) as usize * N;
+            row[l..l + N].copy_from_slice(&fill);
+            row[r..r + N].copy_from_slice(&fill);
+        }
+    });
+}
+impl<'a, I: image::GenericImageView> Rounded<'a, I> {
+    pub fn new(img: &'a I, radius: u32) -> Rounded<'a, I> {
         Rounded {
             origin: img,
             checker: Checker {
@@ -132,33 +90,126 @@ impl Checker {
         false
     }
 }
-impl<'a> image::GenericImageView for Rounded<'a> {
-    type Pixel = image::Rgba<u8>;
+// 保持原图位深，圆角外透明
+impl<'a, I, S> image::GenericImageView for Rounded<'a, I>
+where
+    I: image::GenericImageView,
+    I::Pixel: image::Pixel<Subpixel = S>,
+    S: image::Primitive,
+    image::Rgba<S>: image::Pixel<Subpixel = S>,
+{
+    type Pixel = image::Rgba<S>;
     fn get_pixel(&self, x: u32, y: u32) -> Self::Pixel {
         if self.checker.contains(x, y) {
-            self.origin.get_pixel(x, y)
+            image::Pixel::to_rgba(&self.origin.get_pixel(x, y))
         } else {
-            image::Rgba([0; 4])
+            image::Rgba([image::Primitive::DEFAULT_MIN_VALUE; 4])
         }
     }
     fn dimensions(&self) -> (u32, u32) {
         self.origin.dimensions()
     }
     fn bounds(&self) -> (u32, u32, u32, u32) {
-        self.origin.bounds()
+        let (w, h) = self.dimensions();
+        (0, 0, w, h)
     }
 }
 
 fn dist(p1: (u32, u32), p2: (u32, u32)) -> u32 {
-    let a = if p1.0 > p2.0 {
-        p1.0 - p2.0
-    } else {
-        p2.0 - p1.0
-    };
-    let b = if p1.1 > p2.1 {
-        p1.1 - p2.1
-    } else {
-        p2.1 - p1.1
-    };
+    let a = p1.0.abs_diff(p2.0);
+    let b = p1.1.abs_diff(p2.1);
     a * a + b * b
 }
+
+#[cfg(test)]
+mod test {
+    use image::GenericImageView;
+    use image::Pixel;
+
+    use super::apply;
+    use super::Checker;
+    use super::Rounded;
+
+    #[test]
+    fn round() {
+        let img = image::open("./hello.jpg").unwrap();
+        let mut img = image::DynamicImage::ImageRgba8(
+            img.resize_to_fill(1920, 1080, image::imageops::FilterType::Nearest)
+                .to_rgba8(),
+        );
+        let mut tmp = image::RgbaImage::from_pixel(img.width(), img.height(), image::Rgba([0; 4]));
+        let r = Rounded::new(&img, 40);
+        tmp.enumerate_pixels_mut().for_each(|(x, y, p)| {
+            let v = r.get_pixel(x, y);
+            p.blend(&v);
+        });
+        apply(&mut img, 40);
+        img.save("output_round.png").unwrap();
+        tmp.save("output_round1.png").unwrap();
+    }
+    #[test]
+    fn checker() {
+        let c = Checker {
+            width: 100,
+            height: 100,
+            radius: 30,
+        };
+        assert!(!c.contains(0, 0));
+        assert!(!c.contains(8, 8));
+        assert!(!c.contains(99, 99));
+        assert!(!c.contains(93, 93));
+        assert!(!c.contains(99, 0));
+        assert!(!c.contains(0, 99));
+        assert!(c.contains(50, 50));
+    }
+    #[test]
+    fn gray() {
+        let (w, h) = (40, 30);
+        let c = Checker {
+            width: w,
+            height: h,
+            radius: 10,
+        };
+        let src = image::RgbaImage::from_pixel(w, h, image::Rgba([128, 128, 128, 255]));
+        let src = image::DynamicImage::ImageRgba8(src);
+        let mut imgs = [
+            image::DynamicImage::ImageLuma8(src.to_luma8()),
+            image::DynamicImage::ImageLumaA8(src.to_luma_alpha8()),
+            image::DynamicImage::ImageLuma16(src.to_luma16()),
+            image::DynamicImage::ImageLumaA16(src.to_luma_alpha16()),
+        ];
+        for img in imgs.iter_mut() {
+            apply(img, 10);
+            // 无 alpha 时角外填白，有 alpha 时角外透明
+            let has_alpha = img.color().has_alpha();
+            for (x, y, p) in img.pixels() {
+                let inside = c.contains(x.min(w - x - 1), y.min(h - y - 1));
+                let expect = match (inside, has_alpha) {
+                    (true, _) => [128, 128, 128, 255],
+                    (false, true) => [0; 4],
+                    (false, false) => [255; 4],
+                };
+                assert_eq!(p.0, expect, "{:?} ({x}, {y})", img.color());
+            }
+        }
+    }
+    #[test]
+    fn corners() {
+        let (w, h) = (40, 30);
+        let mut img = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            w,
+            h,
+            image::Rgba([255; 4]),
+        ));
+        apply(&mut img, 10);
+        let c = Checker {
+            width: w,
+            height: h,
+            radius: 10,
+        };
+        for (x, y, p) in img.pixels() {
+            let inside = c.contains(x.min(w - x - 1), y.min(h - y - 1));
+            assert_eq!(p[3] == 255, inside, "({x}, {y})");
+        }
+    }
+}
@@ -1,9 +1,8 @@
-#![allow(unused)]
 use std::time::Instant;
 
-use image::{
-    imageops, DynamicImage, GenericImage, GenericImageView, Pixel, Rgb, RgbImage, Rgba, RgbaImage,
-};
+use image::{GenericImageView, ImageBuffer, Pixel, Rgba};
+
+use crate::depth::{self, Depth};
 
 // https://html.spec.whatwg.org/multipage/canvas.html#when-shadows-are-drawn
 // UNSURPPORTED spread_radius: i32,
@@ -16,6 +15,8 @@ pub struct Shadow {
     blur_radius: u32,
     // rgba
     color: [u8; 4],
+    // 在线性光下混合
+    linear: bool,
 }
 
 impl Default for Shadow {
@@ -25,6 +26,7 @@ impl Default for Shadow {
             offset_y: 10,
             blur_radius: 5,
             color: [0, 0, 0, 255],
+            linear: false,
         }
     }
 }
@@ -52,6 +54,10 @@ impl Builder {
         self.inner.color = rgba;
         self
     }
+    pub fn linear(mut self, linear: bool) -> Builder {
+        self.inner.linear = linear;
+        self
+    }
     pub fn build(self) -> Shadow {
         self.inner
     }
@@ -62,7 +68,12 @@ impl Shadow {
     }
     /// 生成包含阴影的图层，以及原图偏移量（ (0,0)在此图层的位置）
     /// 详见 [When shadows are drawn](https://html.spec.whatwg.org/multipage/canvas.html#when-shadows-are-drawn)
-    pub fn gen_bg<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+    pub fn gen_bg<S, T>(&self, img: &T) -> (ImageBuffer<Rgba<S>, Vec<S>>, u32, u32)
+    where
+        S: Depth,
+        Rgba<S>: Pixel<Subpixel = S>,
+        T: GenericImageView<Pixel = Rgba<S>> + Sync,
+    {
         let get_size = |origin: i32, offset: i32, blur: i32| -> (u32, u32) {
             let s = origin.max(origin + offset + blur) - 0.min(offset - blur);
             let x = if offset < 0 {
@@ -76,65 +87,363 @@ impl Shadow {
             get_size(img.width() as i32, self.offset_x, self.blur_radius as i32);
         let (bg_height, combine_offset_y) =
             get_size(img.height() as i32, self.offset_y, self.blur_radius as i32);
-        let mut b = RgbaImage::from_pixel(
+        let mut b = ImageBuffer::from_pixel(
             bg_width,
             bg_height,
             Rgba({
-                let mut p = self.color;
-                p[3] = 0;
+                let mut p = depth::color::<S>(self.color).0;
+                p[3] = S::DEFAULT_MIN_VALUE;
                 p
             }),
         );
         if self.nop() {
             return (b, combine_offset_x, combine_offset_y);
         }
-        img.pixels().for_each(|(x, y, p)| {
-            let bx = if self.offset_x < 0 {
-                x + combine_offset_x - (-self.offset_x as u32)
-            } else {
-                x + combine_offset_x + (self.offset_x as u32)
-            };
-            let by = if self.offset_y < 0 {
-                y + combine_offset_y - (-self.offset_y as u32)
-            } else {
-                y + combine_offset_y + (self.offset_y as u32)
-            };
-            let tmp = b.get_pixel_mut(bx, by);
-            tmp.0[3] = p[3];
+        // 阴影相对图层的位置
+        let sx = combine_offset_x as i64 + self.offset_x as i64;
+        let sy = combine_offset_y as i64 + self.offset_y as i64;
+        crate::par_rows(&mut b, |by, row| {
+            let y = by as i64 - sy;
+            if y < 0 || y >= img.height() as i64 {
+                return;
+            }
+            for x in 0..img.width() {
+                row[(x as i64 + sx) as usize * 4 + 3] = img.get_pixel(x, y as u32)[3];
+            }
         });
 
         let tt0 = Instant::now();
-        let mut raw: Vec<_> = b.pixels().map(|p| p.0).collect();
-        blurslice::gaussian_blur(
-            &mut raw,
-            b.width() as usize,
-            b.height() as usize,
+        let (w, h) = (b.width() as usize, b.height() as usize);
+        crate::blur::gaussian_blur_premultiplied::<_, 4>(
+            &mut b,
+            w,
+            h,
             self.blur_radius as f32 / 2.0,
         );
-        b.pixels_mut().zip(raw).for_each(|(p, v)| {
-            p.0 = v;
-        });
         // https://github.com/image-rs/image/issues/986
         // let mut b = imageops::blur(&b, self.blur_radius as f32 / 2.0);
         log::info!("imageops::blur cost {}ms", tt0.elapsed().as_millis());
         let alpha = self.color[3] as f64 / 255.0;
+        let max = S::DEFAULT_MAX_VALUE.into_f64();
         let full = self.color[3] == 255;
         if !full {
-            b.pixels_mut().for_each(|p| {
-                let v = p.0[3] as f64 / 255.0;
-                let v = alpha * v * 255.0;
-                p.0[3] = v.ceil() as u8;
+            crate::par_rows(&mut b, |_, row| {
+                row.chunks_exact_mut(4).for_each(|p| {
+                    let v = p[3].into_f64() / max;
+                    p[3] = S::ceil(alpha * v * max);
+                });
             });
         }
         (b, combine_offset_x, combine_offset_y)
     }
 
     /// 生成最终，以及原图偏移量（ (0,0)在此结果图中的位置）
-    pub fn apply<T: GenericImageView<Pixel = Rgba<u8>>>(&self, img: &T) -> (RgbaImage, u32, u32) {
+    pub fn apply<S, T>(&self, img: &T) -> (ImageBuffer<Rgba<S>, Vec<S>>, u32, u32)
+    where
+        S: Depth,
+        Rgba<S>: Pixel<Subpixel = S>,
+        T: GenericImageView<Pixel = Rgba<S>> + Sync,
+    {
         let (mut bg, dx, dy) = self.gen_bg(img);
-        img.pixels().for_each(|(x, y, p)| {
-            bg.get_pixel_mut(x + dx, y + dy).blend(&p);
+        let gamma = self.linear.then(S::gamma);
+        crate::par_rows(&mut bg, |by, row| {
+            if by < dy || by - dy >= img.height() {
+                return;
+            }
+            for x in 0..img.width() {
+                let i = (x + dx) as usize * 4;
+                let p = img.get_pixel(x, by - dy);
+                match gamma {
+                    Some(g) => g.blend(&mut row[i..i + 4], &p),
+                    None => Rgba::from_slice_mut(&mut row[i..i + 4]).blend(&p),
+                }
+            }
         });
         (bg, dx, dy)
     }
+
+    /// 直接在画布上绘制圆角矩形的阴影，(x, y) 为矩形左上角。
+    /// 按解析式逐像素求值，无需生成并模糊整张图层，
+    /// 详见 [Fast Rounded Rectangle Shadows](https://madebyevan.com/shaders/fast-rounded-rectangle-shadows/)
+    pub fn draw_rounded<P, S>(
+        &self,
+        canvas: &mut ImageBuffer<P, Vec<S>>,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+        radius: u32,
+    ) where
+        P: Pixel<Subpixel = S>,
+        S: Depth,
+        Rgba<S>: Pixel<Subpixel = S>,
+    {
+        if self.nop() || width == 0 || height == 0 {
+            return;
+        }
+        // 与 gen_bg 的模糊程度一致
+        let sigma = self.blur_radius as f32 / 2.0;
+        let half = (width as f32 / 2.0, height as f32 / 2.0);
+        let corner = (radius as f32).min(half.0).min(half.1);
+        let center = (
+            (x + self.offset_x) as f32 + half.0,
+            (y + self.offset_y) as f32 + half.1,
+        );
+        // 3 sigma 以外的影响忽略不计
+        let reach = (sigma * 3.0).ceil() as i32 + 1;
+        let x0 = (center.0 - half.0) as i32 - reach;
+        let x1 = (center.0 + half.0) as i32 + reach;
+        let y0 = (center.1 - half.1) as i32 - reach;
+        let y1 = (center.1 + half.1) as i32 + reach;
+        let w = canvas.width();
+        let xs = x0.max(0) as u32..(x1.max(0) as u32).min(w);
+        let alpha = self.color[3] as f32;
+        // 8 位 alpha 换算到画布位深
+        let scale = S::DEFAULT_MAX_VALUE.into_f64() / 255.0;
+        let color = depth::color::<S>(self.color);
+        let gamma = self.linear.then(S::gamma);
+        let n = P::CHANNEL_COUNT as usize;
+        crate::par_rows(canvas, |cy, row| {
+            if (cy as i32) < y0 || cy as i32 >= y1 {
+                return;
+            }
+            let py = cy as f32 + 0.5 - center.1;
+            for cx in xs.clone() {
+                let px = cx as f32 + 0.5 - center.0;
+                let v = rounded_box(px, py, half, corner, sigma);
+                if v <= 0.0 {
+                    continue;
+                }
+                let mut c = color;
+                c[3] = S::from_f64((alpha * v) as f64 * scale);
+                let i = cx as usize * n;
+                match gamma {
+                    Some(g) => g.blend(&mut row[i..i + n], &c),
+                    None => crate::blend_into(&mut row[i..i + n], &c),
+                }
+            }
+        });
+    }
+
+    /// 在画布 (x, y) 处绘制图像及其阴影，效果同 apply 后再混合到画布。
+    /// 按 budget（字节）将画布分成横条，每条只取影响到它的图像行生成阴影图层，
+    /// 结果与整幅绘制一致
+    pub fn draw<P, S, T>(
+        &self,
+        canvas: &mut ImageBuffer<P, Vec<S>>,
+        img: &T,
+        x: i64,
+        y: i64,
+        budget: Option<usize>,
+    ) where
+        P: Pixel<Subpixel = S>,
+        S: Depth,
+        Rgba<S>: Pixel<Subpixel = S>,
+        T: GenericImageView<Pixel = Rgba<S>> + Sync,
+    {
+        let (w, h) = img.dimensions();
+        let ch = canvas.height();
+        // 阴影纵向模糊影响的行数
+        let halo = crate::blur::halo(self.blur_radius as f32 / 2.0) as i64;
+        let oy = self.offset_y as i64;
+        let band = match budget {
+            Some(b) => {
+                // 每行需图像副本、阴影图层，以及模糊时 f32 的预乘副本与转置缓冲
+                let blur = self.blur_radius as usize;
+                let layer = w as usize + self.offset_x.unsigned_abs() as usize + blur * 2;
+                let row_bytes =
+                    (w as usize + layer) * 4 * std::mem::size_of::<S>() + layer * 4 * 4 * 2;
+                let extra = halo as usize * 2 + self.offset_y.unsigned_abs() as usize + blur * 2;
+                (b / row_bytes).saturating_sub(extra).max(1) as u32
+            }
+            None => ch,
+        };
+        let gamma = self.linear.then(S::gamma);
+        let mut c0 = 0;
+        while c0 < ch {
+            let c1 = (c0 + band).min(ch);
+            // 此条内可见的图像行，以及投下阴影的图像行
+            let p0 = (c0 as i64 - y).min(c0 as i64 - y - oy - halo);
+            let p1 = (c1 as i64 - y).max(c1 as i64 - y - oy + halo);
+            let p0 = p0.clamp(0, h as i64) as u32;
+            let p1 = p1.clamp(0, h as i64) as u32;
+            if p0 < p1 {
+                let part = ImageBuffer::from_fn(w, p1 - p0, |px, py| img.get_pixel(px, p0 + py));
+                let (layer, dx, dy) = self.apply(&part);
+                let (lx, ly) = (x - dx as i64, y + p0 as i64 - dy as i64);
+                crate::composite(canvas, &layer, lx, ly, c0..c1, gamma);
+            }
+            c0 = c1;
+        }
+    }
+}
+
+/// 中心位于原点、半边长 half、圆角 corner 的矩形经 sigma 高斯模糊后在 (x, y) 的覆盖率
+fn rounded_box(x: f32, y: f32, half: (f32, f32), corner: f32, sigma: f32) -> f32 {
+    // 到圆角矩形边缘的有向距离，用于跳过完全在内或在外的像素
+    let qx = x.abs() - half.0 + corner;
+    let qy = y.abs() - half.1 + corner;
+    let d = qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) - corner;
+    if sigma < 0.5 {
+        return (0.5 - d).clamp(0.0, 1.0);
+    }
+    if d >= sigma * 3.0 {
+        return 0.0;
+    }
+    if d <= -sigma * 3.0 {
+        return 1.0;
+    }
+    // 纵向偏移 t 处取矩形第 y - t 行的截面，按高斯权重在 ±3 sigma 内积分。
+    // 直边段截面宽度不变，积分即 erf 之差；圆角段截面随行变化，分段数值积分
+    let (lo, hi) = (
+        (-3.0 * sigma).max(y - half.1),
+        (3.0 * sigma).min(y + half.1),
+    );
+    if lo >= hi {
+        return 0.0;
+    }
+    let straight = half.1 - corner;
+    let (s0, s1) = ((y - straight).max(lo), (y + straight).min(hi));
+    let k = std::f32::consts::FRAC_1_SQRT_2 / sigma;
+    let mut value = 0.0;
+    if s1 > s0 {
+        value += box_row(x, 0.0, half, corner, sigma) * 0.5 * (erf(s1 * k) - erf(s0 * k));
+    }
+    for (a, b) in [(lo, s0.min(hi)), (s1.max(lo), hi)] {
+        if b <= a {
+            continue;
+        }
+        let step = (b - a) / CORNER_SAMPLES as f32;
+        for i in 0..CORNER_SAMPLES {
+            let t = a + step * (i as f32 + 0.5);
+            value += box_row(x, y - t, half, corner, sigma) * gaussian(t, sigma) * step;
+        }
+    }
+    value.clamp(0.0, 1.0)
+}
+
+// 每段圆角的积分样本数
+const CORNER_SAMPLES: u32 = 16;
+
+/// 矩形在第 y 行的横向截面经模糊后在 x 处的覆盖率
+fn box_row(x: f32, y: f32, half: (f32, f32), corner: f32, sigma: f32) -> f32 {
+    let delta = (half.1 - corner - y.abs()).min(0.0);
+    let curved = half.0 - corner + (corner * corner - delta * delta).max(0.0).sqrt();
+    let k = std::f32::consts::FRAC_1_SQRT_2 / sigma;
+    0.5 * (erf((x + curved) * k) - erf((x - curved) * k))
+}
+
+fn gaussian(x: f32, sigma: f32) -> f32 {
+    const SQRT_2PI: f32 = 2.506_628_3;
+    (-(x * x) / (2.0 * sigma * sigma)).exp() / (SQRT_2PI * sigma)
+}
+
+/// 误差函数的近似，误差约 5e-4
+fn erf(x: f32) -> f32 {
+    let a = x.abs();
+    let t = 1.0 + (0.278393 + (0.230389 + 0.078108 * a * a) * a) * a;
+    let t = t * t;
+    (1.0 - 1.0 / (t * t)).copysign(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::round::Rounded;
+    use image::{DynamicImage, RgbaImage};
+
+    #[test]
+    fn rounded() {
+        let (w, h, r, m) = (200, 150, 30, 60);
+        let photo = DynamicImage::ImageRgba8(RgbaImage::from_pixel(w, h, Rgba([255; 4])));
+        let shadow = Builder::new()
+            .offset(8, 12)
+            .blur_radius(20)
+            .color([0, 0, 0, 255])
+            .build();
+        let mut fast = RgbaImage::new(w + m * 2, h + m * 2);
+        shadow.draw_rounded(&mut fast, m as i32, m as i32, w, h, r);
+        let mut full = RgbaImage::new(w + m * 2, h + m * 2);
+        let (bg, dx, dy) = shadow.gen_bg(&Rounded::new(&photo, r));
+        crate::overlay(&mut full, &bg, (m - dx) as i32, (m - dy) as i32);
+        let max = fast
+            .pixels()
+            .zip(full.pixels())
+            .map(|(a, b)| a[3].abs_diff(b[3]))
+            .max()
+            .unwrap();
+        // 通用路径以盒式模糊近似高斯，差异主要来自后者，精度见 rounded_exact
+        assert!(max <= 10, "max alpha error {max}");
+        // 矩形中心完全被遮挡，远处没有阴影
+        assert_eq!(fast.get_pixel(m + w / 2, m + h / 2)[3], 255);
+        assert_eq!(fast.get_pixel(0, 0)[3], 0);
+    }
+
+    #[test]
+    fn rounded_exact() {
+        // 与精确结果比较：超采样得到圆角矩形的覆盖率，再做真正的高斯模糊
+        for (w, h, r, blur) in [(200, 150, 30, 20), (120, 90, 45, 40), (80, 80, 10, 6)] {
+            let m = blur * 2;
+            let (cw, ch) = (w + m * 2, h + m * 2);
+            let shadow = Builder::new()
+                .offset(0, 0)
+                .blur_radius(blur)
+                .color([0, 0, 0, 255])
+                .build();
+            let mut fast = RgbaImage::new(cw, ch);
+            shadow.draw_rounded(&mut fast, m as i32, m as i32, w, h, r);
+            let exact = reference(cw, ch, (m, m, w, h), r, blur as f64 / 2.0);
+            let max = fast
+                .pixels()
+                .zip(exact.iter())
+                .map(|(a, b)| (a[3] as f64 - b * 255.0).abs())
+                .fold(0.0, f64::max);
+            assert!(
+                max <= 4.0,
+                "{w}x{h} r{r} blur{blur}: max alpha error {max:.2}"
+            );
+        }
+    }
+
+    /// 圆角矩形 (x, y, w, h) 8x8 超采样的覆盖率，按行列各做一次 sigma 高斯卷积
+    fn reference(cw: u32, ch: u32, rect: (u32, u32, u32, u32), r: u32, sigma: f64) -> Vec<f64> {
+        let (x0, y0, w, h) = rect;
+        let inside = |px: f64, py: f64| {
+            let qx = (px - x0 as f64 - w as f64 / 2.0).abs() - w as f64 / 2.0 + r as f64;
+            let qy = (py - y0 as f64 - h as f64 / 2.0).abs() - h as f64 / 2.0 + r as f64;
+            qx.max(0.0).hypot(qy.max(0.0)) + qx.max(qy).min(0.0) <= r as f64
+        };
+        let (cw, ch) = (cw as usize, ch as usize);
+        let mut cov = vec![0.0; cw * ch];
+        for (i, c) in cov.iter_mut().enumerate() {
+            let (px, py) = ((i % cw) as f64, (i / cw) as f64);
+            let n = (0..64)
+                .filter(|s| {
+                    let (sx, sy) = ((s % 8) as f64 + 0.5, (s / 8) as f64 + 0.5);
+                    inside(px + sx / 8.0, py + sy / 8.0)
+                })
+                .count();
+            *c = n as f64 / 64.0;
+        }
+        let reach = (sigma * 4.0).ceil() as i64;
+        let kernel: Vec<f64> = (-reach..=reach)
+            .map(|d| (-(d * d) as f64 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: f64 = kernel.iter().sum();
+        let conv = |src: &[f64], horizontal: bool| {
+            let mut dst = vec![0.0; cw * ch];
+            for (i, v) in dst.iter_mut().enumerate() {
+                let (x, y) = ((i % cw) as i64, (i / cw) as i64);
+                for (j, k) in kernel.iter().enumerate() {
+                    let d = j as i64 - reach;
+                    let (sx, sy) = if horizontal { (x + d, y) } else { (x, y + d) };
+                    if sx >= 0 && sy >= 0 && sx < cw as i64 && sy < ch as i64 {
+                        *v += src[sy as usize * cw + sx as usize] * k / total;
+                    }
+                }
+            }
+            dst
+        };
+        conv(&conv(&cov, true), false)
+    }
 }
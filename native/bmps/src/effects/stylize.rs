@@ -0,0 +1,186 @@
+//! 风格化背景：马赛克、双色调与毛玻璃。
+//! 马赛克直接由背景源生成，另两种作用于模糊后的背景
+use image::{imageops::FilterType, DynamicImage, ImageBuffer, Pixel, Rgb};
+use rayon::prelude::*;
+
+use crate::config::{BackgroundMode, Grain, Tone};
+use crate::depth::{self, Depth};
+
+const LUMA: [f64; 3] = [0.2126, 0.7152, 0.0722];
+
+/// 按 mode 原地处理模糊后的背景，photo 用于取色。马赛克与边缘延伸不在此处理
+pub fn apply<S: Depth>(
+    bg: &mut ImageBuffer<Rgb<S>, Vec<S>>,
+    mode: &BackgroundMode,
+    photo: &DynamicImage,
+) where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    match *mode {
+        BackgroundMode::Duotone { dark, light } => {
+            let (d, l) = match (dark, light) {
+                (Some(d), Some(l)) => (d, l),
+                _ => {
+                    let (d, l) = palette(photo);
+                    (dark.unwrap_or(d), light.unwrap_or(l))
+                }
+            };
+            duotone(bg, d, l);
+        }
+        BackgroundMode::Frosted {
+            saturation,
+            noise,
+            tint,
+        } => {
+            let tone = Tone {
+                saturation,
+                tint,
+                ..Default::default()
+            };
+            super::tone::apply(bg, &tone);
+            let grain = Grain {
+                strength: noise,
+                ..Default::default()
+            };
+            super::grain::apply(bg, &grain);
+        }
+        _ => {}
+    }
+}
+
+/// 将 src 缩放裁剪至 width x height 的马赛克，方格居中排列，每格取区域均值
+pub fn mosaic<S: Depth>(
+    src: &DynamicImage,
+    width: u32,
+    height: u32,
+    cell: u32,
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let cell = cell.max(1);
+    let (cols, rows) = (width.div_ceil(cell), height.div_ceil(cell));
+    let small = S::rgb(src.resize_to_fill(cols, rows, FilterType::Triangle));
+    // 画布不是方格的整数倍时，两侧各露出半格
+    let (ox, oy) = ((cols * cell - width) / 2, (rows * cell - height) / 2);
+    let (sw, sh) = (small.width() - 1, small.height() - 1);
+    let mut bg = ImageBuffer::new(width, height);
+    crate::par_rows(&mut bg, |y, row| {
+        let sy = ((y + oy) / cell).min(sh);
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            let sx = ((x as u32 + ox) / cell).min(sw);
+            p.copy_from_slice(&small.get_pixel(sx, sy).0);
+        }
+    });
+    bg
+}
+
+/// 按亮度将每个像素映射到 dark 与 light 之间。模糊后的亮度集中在中间，
+/// 先拉伸到图像自身的亮度范围
+pub fn duotone<S: Depth>(img: &mut ImageBuffer<Rgb<S>, Vec<S>>, dark: [u8; 3], light: [u8; 3])
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let max = S::DEFAULT_MAX_VALUE.into_f64();
+    let to_s = |c: [u8; 3]| {
+        depth::color::<S>([c[0], c[1], c[2], 255])
+            .0
+            .map(|v| v.into_f64())
+    };
+    let (d, l) = (to_s(dark), to_s(light));
+    let luma = |p: &[S]| (0..3).map(|c| p[c].into_f64() * LUMA[c]).sum::<f64>() / max;
+    let (lo, hi) = img
+        .par_chunks(3)
+        .map(luma)
+        .fold(
+            || (f64::MAX, f64::MIN),
+            |(lo, hi), t| (lo.min(t), hi.max(t)),
+        )
+        .reduce(|| (f64::MAX, f64::MIN), |a, b| (a.0.min(b.0), a.1.max(b.1)));
+    let range = if hi - lo > 1e-3 {
+        (lo, hi - lo)
+    } else {
+        (0.0, 1.0)
+    };
+    crate::par_rows(img, |_, row| {
+        for p in row.chunks_exact_mut(3) {
+            let t = ((luma(p) - range.0) / range.1).clamp(0.0, 1.0);
+            for c in 0..3 {
+                p[c] = S::from_f64(d[c] + (l[c] - d[c]) * t);
+            }
+        }
+    });
+}
+
+/// 照片的暗部与亮部颜色：亮度最低、最高各四分之一像素的平均色
+pub fn palette(photo: &DynamicImage) -> ([u8; 3], [u8; 3]) {
+    let colors = colors(photo, 4);
+    (colors[0], colors[3])
+}
+
+/// 按亮度将照片像素均分为 n 组，返回各组的平均色，由暗到亮
+pub fn colors(photo: &DynamicImage, n: usize) -> Vec<[u8; 3]> {
+    let thumb = photo.thumbnail(64, 64).into_rgb8();
+    let mut pixels: Vec<([u8; 3], f64)> = thumb
+        .pixels()
+        .map(|p| {
+            let l = (0..3).map(|c| p[c] as f64 * LUMA[c]).sum();
+            (p.0, l)
+        })
+        .collect();
+    pixels.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let n = n.clamp(1, pixels.len().max(1));
+    (0..n)
+        .map(|i| {
+            let part = &pixels[i * pixels.len() / n..(i + 1) * pixels.len() / n];
+            let mut sum = [0f64; 3];
+            for (p, _) in part {
+                for c in 0..3 {
+                    sum[c] += p[c] as f64;
+                }
+            }
+            sum.map(|v| (v / part.len().max(1) as f64).round() as u8)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    #[test]
+    fn mosaic_cells() {
+        let src = DynamicImage::ImageRgb8(RgbImage::from_fn(200, 100, |x, y| {
+            Rgb([x as u8, y as u8 * 2, 100])
+        }));
+        let bg: RgbImage = mosaic(&src, 100, 50, 16);
+        // 7 x 4 格，左右各露出 6 像素、上下各 7 像素
+        assert_eq!(bg.get_pixel(0, 0), bg.get_pixel(9, 8));
+        assert_ne!(bg.get_pixel(9, 8), bg.get_pixel(10, 8));
+        assert_eq!(bg.get_pixel(10, 9), bg.get_pixel(25, 24));
+        assert_ne!(bg.get_pixel(25, 24), bg.get_pixel(25, 25));
+    }
+
+    #[test]
+    fn duotone_palette() {
+        let photo = DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, _| {
+            if x < 32 {
+                Rgb([20, 10, 60])
+            } else {
+                Rgb([250, 220, 150])
+            }
+        }));
+        let (dark, light) = palette(&photo);
+        assert_eq!(dark, [20, 10, 60]);
+        assert_eq!(light, [250, 220, 150]);
+        let mut img = ImageBuffer::<Rgb<u16>, _>::from_fn(3, 1, |x, _| {
+            Rgb([[0, 32767, 65535][x as usize]; 3])
+        });
+        duotone(&mut img, dark, light);
+        assert_eq!(img.get_pixel(0, 0).0, [20 * 257, 10 * 257, 60 * 257]);
+        assert_eq!(img.get_pixel(2, 0).0, [250 * 257, 220 * 257, 150 * 257]);
+        let mid = img.get_pixel(1, 0);
+        assert!(mid[0] > 20 * 257 && mid[0] < 250 * 257);
+    }
+}
@@ -0,0 +1,103 @@
+//! 背景色调调整：曝光、饱和度、叠色与暗角
+use image::{ImageBuffer, Pixel, Rgb};
+
+use crate::config::Tone;
+use crate::depth::Depth;
+use crate::gamma;
+
+/// 按 tone 原地调整 RGB 图像，依次为曝光、饱和度、叠色、暗角
+pub fn apply<S: Depth>(img: &mut ImageBuffer<Rgb<S>, Vec<S>>, tone: &Tone)
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    if *tone == Tone::default() {
+        return;
+    }
+    let (w, h) = img.dimensions();
+    let max = S::DEFAULT_MAX_VALUE.into_f64() as f32;
+    let gamma = S::gamma();
+    let gain = 2f32.powf(tone.exposure);
+    let tint = tone.tint.map(|v| v as f32 / 255.0);
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let far = cx.hypot(cy).max(1.0);
+    crate::par_rows(img, |y, row| {
+        let dy = y as f32 + 0.5 - cy;
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            // 暗角自对角线的 40% 处开始平滑变暗
+            let d = (x as f32 + 0.5 - cx).hypot(dy) / far;
+            let t = ((d - 0.4) / 0.6).clamp(0.0, 1.0);
+            let scale = gain * (1.0 - tone.vignette * t * t * (3.0 - 2.0 * t));
+            // 曝光与暗角在线性光下缩放
+            let mut c = [0f32; 3];
+            for (c, v) in c.iter_mut().zip(p.iter()) {
+                *c = if scale == 1.0 {
+                    v.into_f64() as f32 / max
+                } else {
+                    gamma::encode(gamma.to_linear(*v) * scale)
+                };
+            }
+            let l = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+            for (i, v) in p.iter_mut().enumerate() {
+                let s = l + (c[i] - l) * tone.saturation;
+                let s = s * (1.0 - tint[3]) + tint[i] * tint[3];
+                *v = S::from_f64((s.max(0.0) * max) as f64);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(60, 40, |x, y| Rgb([x as u8 * 4, y as u8 * 6, 200]))
+    }
+
+    #[test]
+    fn neutral() {
+        let mut img = gradient();
+        apply(&mut img, &Tone::default());
+        assert!(img == gradient());
+        let tone = Tone {
+            saturation: 0.0,
+            ..Default::default()
+        };
+        apply(&mut img, &tone);
+        assert!(img
+            .pixels()
+            .all(|p| p[0].abs_diff(p[1]) <= 1 && p[1] == p[2]));
+    }
+
+    #[test]
+    fn exposure() {
+        // 减一档即线性光减半
+        let mut img = RgbImage::from_pixel(4, 4, Rgb([255; 3]));
+        let tone = Tone {
+            exposure: -1.0,
+            ..Default::default()
+        };
+        apply(&mut img, &tone);
+        assert_eq!(img.get_pixel(0, 0).0, [188; 3]);
+    }
+
+    #[test]
+    fn vignette_tint() {
+        let mut img = RgbImage::from_pixel(100, 60, Rgb([200; 3]));
+        let tone = Tone {
+            vignette: 1.0,
+            ..Default::default()
+        };
+        apply(&mut img, &tone);
+        assert_eq!(img.get_pixel(50, 30).0, [200; 3]);
+        assert!(img.get_pixel(0, 0)[0] < 20);
+        assert!(img.get_pixel(0, 30)[0] > img.get_pixel(0, 0)[0]);
+        let tone = Tone {
+            tint: [255, 0, 0, 255],
+            ..Default::default()
+        };
+        apply(&mut img, &tone);
+        assert!(img.pixels().all(|p| p.0 == [255, 0, 0]));
+    }
+}
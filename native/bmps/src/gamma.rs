@@ -0,0 +1,179 @@
+//! sRGB 编码值与线性光之间的转换。直接对 sRGB 值模糊、混合会使边缘偏暗，
+//! 阴影过渡发灰，线性光模式下先解码再计算，最后重新编码
+use image::{Pixel, Rgba};
+use rayon::prelude::*;
+
+use crate::depth::Depth;
+
+/// sRGB 编码值转为线性光，取值 [0, 1]
+pub fn decode(v: f32) -> f32 {
+    let v = v.max(0.0);
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// 线性光转为 sRGB 编码值，取值 [0, 1]
+pub fn encode(v: f32) -> f32 {
+    let v = v.max(0.0);
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// 按位深查表的转换器，整数位深预先算好每个取值，浮点位深直接计算。
+/// 16 位的表较大，渲染中使用 Depth::gamma 共享的实例
+pub struct Gamma<S> {
+    // 编码值到线性光
+    linear: Vec<f32>,
+    // 以 S 存储的线性值到编码值
+    srgb: Vec<S>,
+}
+
+impl<S: Depth> Default for Gamma<S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: Depth> Gamma<S> {
+    pub fn new() -> Gamma<S> {
+        let max = S::DEFAULT_MAX_VALUE.into_f64();
+        let (linear, srgb) = if max > 1.0 {
+            let n = max as usize + 1;
+            let linear = (0..n).map(|i| decode((i as f64 / max) as f32)).collect();
+            let srgb = (0..n)
+                .map(|i| S::from_f64(encode((i as f64 / max) as f32) as f64 * max))
+                .collect();
+            (linear, srgb)
+        } else {
+            (vec![], vec![])
+        };
+        Gamma { linear, srgb }
+    }
+
+    fn max() -> f32 {
+        S::DEFAULT_MAX_VALUE.into_f64() as f32
+    }
+
+    /// 编码值转为线性光，取值 [0, 1]
+    pub fn to_linear(&self, v: S) -> f32 {
+        if self.linear.is_empty() {
+            decode(v.into_f64() as f32 / Self::max())
+        } else {
+            self.linear[v.into_f64() as usize]
+        }
+    }
+
+    /// 线性光转为编码值
+    pub fn to_srgb(&self, v: f32) -> S {
+        S::from_f64((encode(v) * Self::max()) as f64)
+    }
+
+    /// 编码值转为以位深 L 存储的线性值
+    pub fn decode_to<L: Depth>(&self, v: S) -> L {
+        L::from_f64(self.to_linear(v) as f64 * L::DEFAULT_MAX_VALUE.into_f64())
+    }
+
+    /// 以 S 存储的线性值转回编码值
+    pub fn encode_value(&self, v: S) -> S {
+        if self.srgb.is_empty() {
+            self.to_srgb(v.into_f64() as f32 / Self::max())
+        } else {
+            self.srgb[v.into_f64() as usize]
+        }
+    }
+
+    /// 原地将编码值转为以 S 存储的线性值
+    pub fn decode_slice(&self, data: &mut [S]) {
+        data.par_iter_mut().for_each(|v| *v = self.decode_to(*v));
+    }
+
+    /// 原地将以 S 存储的线性值转回编码值
+    pub fn encode_slice(&self, data: &mut [S]) {
+        data.par_iter_mut().for_each(|v| *v = self.encode_value(*v));
+    }
+
+    /// 在线性光下将 src 混合到 RGB 或 RGBA 像素的子像素上，同 Pixel::blend
+    pub fn blend(&self, dst: &mut [S], src: &Rgba<S>)
+    where
+        Rgba<S>: Pixel<Subpixel = S>,
+    {
+        let max = Self::max();
+        let sa = src[3].into_f64() as f32 / max;
+        if sa <= 0.0 {
+            return;
+        }
+        let da = dst.get(3).map_or(1.0, |a| a.into_f64() as f32 / max);
+        let out = sa + da * (1.0 - sa);
+        if sa >= 1.0 || out <= 0.0 {
+            let n = dst.len();
+            dst.copy_from_slice(&src.0[..n]);
+            return;
+        }
+        for c in 0..3 {
+            let s = self.to_linear(src[c]) * sa;
+            let d = self.to_linear(dst[c]) * da * (1.0 - sa);
+            dst[c] = self.to_srgb((s + d) / out);
+        }
+        if let Some(a) = dst.get_mut(3) {
+            *a = S::from_f64((out * max) as f64);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let g = Gamma::<u8>::new();
+        for v in 0..=255u8 {
+            assert_eq!(g.to_srgb(g.to_linear(v)), v);
+        }
+        // 8 位照片的线性值以 16 位存储，转回 8 位后不变
+        let mut data: Vec<u16> = (0..=255u16).map(|v| v * 257).collect();
+        let g = Gamma::<u16>::new();
+        g.decode_slice(&mut data);
+        g.encode_slice(&mut data);
+        for (v, d) in data.iter().enumerate() {
+            assert_eq!((*d as f32 / 257.0).round() as usize, v);
+        }
+        // 16 位照片的线性值以浮点存储，暗部也只差取整误差
+        let src: Vec<u16> = (0..=u16::MAX).step_by(7).collect();
+        for &v in src.iter() {
+            assert!(g.to_srgb(g.to_linear(v)).abs_diff(v) <= 1, "{v}");
+        }
+        let mut data: Vec<f32> = src.iter().map(|&v| v as f32 / 65535.0).collect();
+        let g = Gamma::<f32>::new();
+        g.decode_slice(&mut data);
+        g.encode_slice(&mut data);
+        let max = src
+            .iter()
+            .zip(data.iter())
+            .map(|(a, b)| a.abs_diff((b * 65535.0).round() as u16))
+            .max();
+        assert!(max.unwrap() <= 1, "{max:?}");
+        assert!((encode(decode(0.5)) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn blend() {
+        // 半透明白色叠在黑色上，线性光下为 50% 亮度，sRGB 编码约为 188
+        let g = Gamma::<u8>::new();
+        let mut dst = [0u8, 0, 0];
+        g.blend(&mut dst, &Rgba([255, 255, 255, 128]));
+        assert!(dst.iter().all(|&v| (187..=189).contains(&v)), "{dst:?}");
+        // 不透明或全透明时与 Pixel::blend 一致
+        let mut dst = [10u8, 20, 30, 255];
+        g.blend(&mut dst, &Rgba([1, 2, 3, 0]));
+        assert_eq!(dst, [10, 20, 30, 255]);
+        g.blend(&mut dst, &Rgba([1, 2, 3, 255]));
+        assert_eq!(dst, [1, 2, 3, 255]);
+    }
+}
@@ -1,9 +1,28 @@
+use blur::Sample;
 pub use config::Config;
-use image::{imageops::FilterType, DynamicImage, GenericImage, GenericImageView, Pixel};
-use std::{path::Path, sync::OnceLock, time::Instant};
+use depth::Depth;
+use gamma::Gamma;
+use image::{
+    ColorType, DynamicImage, GenericImage, GenericImageView, ImageBuffer, ImageFormat, Pixel,
+    Primitive, Rgb, Rgba, RgbaImage,
+};
+use rayon::prelude::*;
+use std::{
+    borrow::Cow,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Instant,
+};
 
+pub mod blur;
 pub mod config;
+pub mod depth;
 pub mod effects;
+pub mod gamma;
+pub mod procedural;
+pub mod styles;
+pub mod text;
 pub use log;
 
 pub fn round_img(origin: String, output: String, radius: u32) -> anyhow::Result<()> {
@@ -13,22 +32,332 @@ pub fn round_img(origin: String, output: String, radius: u32) -> anyhow::Result<
     Ok(())
 }
 
-fn blur(radius: f32, img: DynamicImage) -> DynamicImage {
+/// 背景模糊的参数
+#[derive(Clone, Copy, Debug, Default)]
+struct Blur {
+    radius: f32,
+    style: config::BlurStyle,
+    // 图像边缘的处理方式
+    edge: blur::Edge,
+}
+
+/// 原地模糊，按内存预算分条。linear 时每条先解码为以 S::Linear 存储的线性光，
+/// 模糊后编码写回，不产生整幅的线性光副本
+fn blur<S: Depth>(
+    spec: Blur,
+    img: &mut ImageBuffer<Rgb<S>, Vec<S>>,
+    budget: Option<usize>,
+    linear: bool,
+) where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
     let s = Instant::now();
-    let w = img.width();
-    let h = img.height();
-    let mut data: Vec<[u8; 3]> = img.clone().into_rgb8().pixels().map(|p| p.0).collect();
-    blurslice::gaussian_blur(&mut data, w as usize, h as usize, radius);
-    let i = image::RgbImage::from_raw(w, h, data.into_iter().flatten().collect()).unwrap();
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let Blur {
+        radius,
+        style,
+        edge,
+    } = spec;
+    match style {
+        _ if linear => blur_linear(spec, img, budget),
+        config::BlurStyle::Gaussian => {
+            blur::gaussian_blur_banded::<_, 3>(img, w, h, radius, edge, budget)
+        }
+        _ => styles::apply_banded(&style, img, w, h, radius, edge, budget),
+    }
     log::info!("blur cost: {}ms", s.elapsed().as_millis());
-    DynamicImage::ImageRgb8(i)
+}
+
+fn blur_linear<S: Depth>(spec: Blur, img: &mut ImageBuffer<Rgb<S>, Vec<S>>, budget: Option<usize>)
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let stride = w * 3;
+    let Blur {
+        radius,
+        style,
+        edge,
+    } = spec;
+    let (gamma, linear) = (S::gamma(), S::Linear::gamma());
+    let (halo, extra) = match style {
+        config::BlurStyle::Gaussian => (blur::halo(radius), 0),
+        _ => (
+            styles::halo(&style, radius),
+            styles::scratch(&style, w, radius),
+        ),
+    };
+    // 每行需要条带副本、线性光副本，以及转置缓冲或输出行
+    let row_bytes =
+        stride * (std::mem::size_of::<S>() + std::mem::size_of::<S::Linear>() * 2) + extra;
+    let budget = budget.filter(|_| edge != blur::Edge::Wrap);
+    let rows = blur::band_rows(budget, row_bytes, halo, h);
+    blur::for_each_band(img, stride, rows, halo, |ext, start, dst, y0| {
+        let mut lin: Vec<S::Linear> = ext.par_iter().map(|&v| gamma.decode_to(v)).collect();
+        let mut out = vec![];
+        let res = match style {
+            config::BlurStyle::Gaussian => {
+                blur::gaussian_blur_edge::<_, 3>(&mut lin, w, ext.len() / stride, radius, edge);
+                &lin[(y0 - start) * stride..][..dst.len()]
+            }
+            _ => {
+                out.resize(dst.len(), Default::default());
+                let src = styles::Src {
+                    data: &lin,
+                    start,
+                    width: w,
+                    height: h,
+                    edge,
+                };
+                styles::band(&style, &src, &mut out, y0, radius);
+                &out
+            }
+        };
+        dst.par_iter_mut()
+            .zip(res.par_iter())
+            .for_each(|(d, &v)| *d = depth::convert(linear.encode_value(v)));
+    });
+}
+
+// 缩小后的模糊半径下限。大半径高斯模糊的结果很平滑，
+// 先缩小 k 倍、以 radius/k 模糊再双线性放大，与全尺寸模糊相比
+// 每个通道的误差不超过 FAST_BLUR_MAX_ERROR（由测试 fast_blur 保证）。
+// 半径过小时盒式近似的取整误差明显，故缩小后半径不低于 16。
+// 光斑、运动、放射模糊的细节随缩放改变，只在全尺寸下模糊
+const MIN_SCALED_RADIUS: f32 = 16.0;
+pub const FAST_BLUR_MAX_ERROR: u8 = 6;
+
+fn downscale_factor(spec: Blur) -> u32 {
+    match spec.style {
+        config::BlurStyle::Gaussian => (spec.radius / MIN_SCALED_RADIUS).floor().max(1.0) as u32,
+        _ => 1,
+    }
+}
+
+/// 缩放裁剪至 width x height 并模糊，大半径的高斯模糊在缩小的图上进行，
+/// 否则按内存预算分条模糊。linear 时在线性光下模糊。
+/// 缩小的图计入内存预算，此外不产生整幅的中间图像
+fn blur_to_fill<S: Depth>(
+    img: &DynamicImage,
+    width: u32,
+    height: u32,
+    spec: Blur,
+    budget: Option<usize>,
+    linear: bool,
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+    Rgb<S::Linear>: Pixel<Subpixel = S::Linear>,
+{
+    let k = downscale_factor(spec);
+    if k == 1 {
+        let mut bg = fill::<S>(img, width, height);
+        blur(spec, &mut bg, budget, linear);
+        return bg;
+    }
+    let s = Instant::now();
+    let (sw, sh) = (width.div_ceil(k), height.div_ceil(k));
+    let spec = Blur {
+        radius: spec.radius * sw as f32 / width as f32,
+        ..spec
+    };
+    let res = if linear {
+        // 缩小的图直接以线性光存储，放大时编码
+        let mut small = fill::<S::Linear>(img, sw, sh);
+        let gamma = S::Linear::gamma();
+        gamma.decode_slice(&mut small);
+        let budget = budget.map(|b| b.saturating_sub(std::mem::size_of_val(small.as_raw())));
+        blur(spec, &mut small, budget, false);
+        upscale(&small, width, height, |v| {
+            depth::convert(gamma.encode_value(S::Linear::from_f64(v as f64)))
+        })
+    } else {
+        let mut small = fill::<S>(img, sw, sh);
+        let budget = budget.map(|b| b.saturating_sub(std::mem::size_of_val(small.as_raw())));
+        blur(spec, &mut small, budget, false);
+        upscale(&small, width, height, |v| S::from_f64(v as f64))
+    };
+    log::info!("blur_to_fill cost: {}ms, k={k}", s.elapsed().as_millis());
+    res
+}
+
+/// 对 DynamicImage 的各种像素格式调用同一泛型表达式，p 为对应的 ImageBuffer
+macro_rules! each_format {
+    ($img:expr, $p:ident => $e:expr) => {
+        match $img {
+            DynamicImage::ImageLuma8($p) => $e,
+            DynamicImage::ImageLumaA8($p) => $e,
+            DynamicImage::ImageRgb8($p) => $e,
+            DynamicImage::ImageRgba8($p) => $e,
+            DynamicImage::ImageLuma16($p) => $e,
+            DynamicImage::ImageLumaA16($p) => $e,
+            DynamicImage::ImageRgb16($p) => $e,
+            DynamicImage::ImageRgba16($p) => $e,
+            DynamicImage::ImageRgb32F($p) => $e,
+            DynamicImage::ImageRgba32F($p) => $e,
+            img => {
+                let $p = &img.to_rgba32f();
+                $e
+            }
+        }
+    };
+}
+
+/// 按比例缩放并居中裁剪至 width x height 时，输出各列（或各行）对应的源像素范围。
+/// 缩小时为输出像素覆盖的区域，放大时为最近的一两个像素
+fn fill_spans(src: u32, scaled: u32, len: u32) -> Vec<Range<u32>> {
+    let offset = scaled.saturating_sub(len) / 2;
+    let scale = src as f64 / scaled as f64;
+    (0..len)
+        .map(|i| {
+            let u = (i + offset) as f64;
+            let a = ((u * scale).floor() as u32).min(src - 1);
+            let b = (((u + 1.0) * scale).ceil() as u32).clamp(a + 1, src);
+            a..b
+        })
+        .collect()
+}
+
+/// 缩放并居中裁剪至 width x height，同 resize_to_fill。直接写入 S 位深的 RGB 缓冲，
+/// 不经过 image 缩放所用的整幅浮点中间图像。缩小时取覆盖区域的平均值
+fn fill<S: Depth>(img: &DynamicImage, width: u32, height: u32) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    fn go<P, S>(
+        src: &ImageBuffer<P, Vec<P::Subpixel>>,
+        width: u32,
+        height: u32,
+    ) -> ImageBuffer<Rgb<S>, Vec<S>>
+    where
+        P: Pixel + Sync,
+        P::Subpixel: Depth,
+        S: Depth,
+        Rgb<S>: Pixel<Subpixel = S>,
+    {
+        let (sw, sh) = src.dimensions();
+        let ratio = (width as f64 / sw as f64).max(height as f64 / sh as f64);
+        let scaled = |v: u32| ((v as f64 * ratio).round() as u32).max(1);
+        let xs = fill_spans(sw, scaled(sw), width);
+        let ys = fill_spans(sh, scaled(sh), height);
+        let mut res = ImageBuffer::new(width, height);
+        par_rows(&mut res, |y, row| {
+            let ys = ys[y as usize].clone();
+            for (p, xs) in row.chunks_exact_mut(3).zip(xs.iter()) {
+                let mut sum = [0f64; 3];
+                for sy in ys.clone() {
+                    for sx in xs.clone() {
+                        let c = src.get_pixel(sx, sy).to_rgb();
+                        for (s, v) in sum.iter_mut().zip(c.0) {
+                            *s += v.into_f64();
+                        }
+                    }
+                }
+                let n = (xs.len() * ys.len()) as f64;
+                for (p, s) in p.iter_mut().zip(sum) {
+                    *p = depth::convert(P::Subpixel::from_f64(s / n));
+                }
+            }
+        });
+        res
+    }
+    each_format!(img, p => go(p, width, height))
+}
+
+/// 保持宽高比最近邻缩放至 width x height 以内，同 resize。
+/// 保留原图的像素格式，不经过浮点中间图像
+fn resize_nearest(img: DynamicImage, width: u32, height: u32) -> DynamicImage {
+    fn go<P: Pixel>(
+        src: &ImageBuffer<P, Vec<P::Subpixel>>,
+        width: u32,
+        height: u32,
+    ) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let (sw, sh) = src.dimensions();
+        let pick = |d: u32, dst: u32, src: u32| {
+            (((d as f64 + 0.5) * src as f64 / dst as f64) as u32).min(src - 1)
+        };
+        let xs: Vec<u32> = (0..width).map(|x| pick(x, width, sw)).collect();
+        ImageBuffer::from_fn(width, height, |x, y| {
+            *src.get_pixel(xs[x as usize], pick(y, height, sh))
+        })
+    }
+    let (sw, sh) = img.dimensions();
+    let ratio = (width as f64 / sw as f64).min(height as f64 / sh as f64);
+    let scaled = |v: u32| ((v as f64 * ratio).round() as u32).max(1);
+    let (width, height) = (scaled(sw), scaled(sh));
+    if (width, height) == (sw, sh) {
+        return img;
+    }
+    each_format!(&img, p => go(p, width, height).into())
+}
+
+/// 双线性放大，像素中心对齐；边缘半个像素内线性外推，避免边缘的平坦色带。
+/// store 将插值结果转为输出位深
+fn upscale<T, S, F>(
+    small: &ImageBuffer<Rgb<T>, Vec<T>>,
+    width: u32,
+    height: u32,
+    store: F,
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    T: Depth,
+    S: Depth,
+    Rgb<T>: Pixel<Subpixel = T>,
+    Rgb<S>: Pixel<Subpixel = S>,
+    F: Fn(f32) -> S + Sync,
+{
+    let (sw, sh) = small.dimensions();
+    let coord = |d: u32, dst: u32, src: u32| -> (u32, u32, f32) {
+        if src == 1 {
+            return (0, 0, 0.0);
+        }
+        let f = (d as f32 + 0.5) * src as f32 / dst as f32 - 0.5;
+        let i = (f.floor().max(0.0) as u32).min(src - 2);
+        (i, i + 1, f - i as f32)
+    };
+    let xs: Vec<_> = (0..width).map(|x| coord(x, width, sw)).collect();
+    let mut res = ImageBuffer::new(width, height);
+    let stride = sw as usize * 3;
+    let src_row = |y: u32| &small.as_raw()[y as usize * stride..][..stride];
+    par_rows(&mut res, |y, row| {
+        // 先在竖直方向插值出一行，再逐像素水平插值
+        let (y0, y1, fy) = coord(y, height, sh);
+        let line: Vec<f32> = src_row(y0)
+            .iter()
+            .zip(src_row(y1))
+            .map(|(a, b)| {
+                let a = a.into_f64() as f32;
+                a + (b.into_f64() as f32 - a) * fy
+            })
+            .collect();
+        for (p, &(x0, x1, fx)) in row.chunks_exact_mut(3).zip(xs.iter()) {
+            let a = &line[x0 as usize * 3..][..3];
+            let b = &line[x1 as usize * 3..][..3];
+            for i in 0..3 {
+                p[i] = store(a[i] + (b[i] - a[i]) * fx);
+            }
+        }
+    });
+    res
 }
 
 pub fn blur_img(radius: f32, origin: String, out: String) -> anyhow::Result<()> {
     let img = open_img(origin)?;
-    let bg = blur(radius, img);
-    bg.save(out)?;
-    Ok(())
+    let (w, h) = img.dimensions();
+    let spec = Blur {
+        radius,
+        ..Default::default()
+    };
+    let bg = match img.color() {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            u16::dynamic(blur_to_fill(&img, w, h, spec, None, false))
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => {
+            f32::dynamic(blur_to_fill(&img, w, h, spec, None, false))
+        }
+        _ => u8::dynamic(blur_to_fill(&img, w, h, spec, None, false)),
+    };
+    save(&bg, out)
 }
 
 static FONT_FAMILIES: OnceLock<Vec<String>> = OnceLock::new();
@@ -42,14 +371,17 @@ pub fn font_families() -> Vec<String> {
     });
     res.clone()
 }
+fn read_exif<P: AsRef<Path>>(path: P) -> anyhow::Result<exif::Exif> {
+    let file = std::fs::File::open(path)?;
+    let mut bufreader = std::io::BufReader::new(&file);
+    let exifreader = exif::Reader::new();
+    Ok(exifreader.read_from_container(&mut bufreader)?)
+}
 // https://magnushoff.com/articles/jpeg-orientation/
 fn get_orientation<P: AsRef<Path>>(path: P) -> anyhow::Result<u32> {
     use exif::In;
     use exif::Tag;
-    let file = std::fs::File::open(path)?;
-    let mut bufreader = std::io::BufReader::new(&file);
-    let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader)?;
+    let exif = read_exif(path)?;
     match exif.get_field(Tag::Orientation, In::PRIMARY) {
         Some(orientation) => match orientation.value.get_uint(0) {
             Some(v @ 1..=8) => Ok(v),
@@ -58,6 +390,105 @@ fn get_orientation<P: AsRef<Path>>(path: P) -> anyhow::Result<u32> {
         None => Err(anyhow::Error::msg("orientation missing")),
     }
 }
+// 拍摄时间，缺少 DateTimeOriginal 时退回 DateTime
+fn get_date<P: AsRef<Path>>(path: P) -> anyhow::Result<exif::DateTime> {
+    use exif::In;
+    use exif::Tag;
+    let exif = read_exif(path)?;
+    let field = exif
+        .get_field(Tag::DateTimeOriginal, In::PRIMARY)
+        .or_else(|| exif.get_field(Tag::DateTime, In::PRIMARY))
+        .ok_or_else(|| anyhow::Error::msg("date missing"))?;
+    match &field.value {
+        exif::Value::Ascii(v) if !v.is_empty() => Ok(exif::DateTime::from_ascii(&v[0])?),
+        _ => Err(anyhow::Error::msg("date invalid")),
+    }
+}
+
+/// 以 (x, y) 为左上角将图层混合到画布
+pub(crate) fn overlay<I: GenericImage<Pixel = Rgba<u8>>>(
+    canvas: &mut I,
+    layer: &RgbaImage,
+    x: i32,
+    y: i32,
+) {
+    let (w, h) = canvas.dimensions();
+    for (lx, ly, p) in layer.enumerate_pixels() {
+        let cx = x + lx as i32;
+        let cy = y + ly as i32;
+        if p.0[3] == 0 || cx < 0 || cy < 0 || cx as u32 >= w || cy as u32 >= h {
+            continue;
+        }
+        let mut b = canvas.get_pixel(cx as u32, cy as u32);
+        b.blend(p);
+        canvas.put_pixel(cx as u32, cy as u32, b);
+    }
+}
+
+/// 将图像按行分块交给线程池并行处理，回调参数为行号及该行的子像素
+pub(crate) fn par_rows<P, F>(img: &mut ImageBuffer<P, Vec<P::Subpixel>>, f: F)
+where
+    P: Pixel,
+    P::Subpixel: Send,
+    F: Fn(u32, &mut [P::Subpixel]) + Sync,
+{
+    let stride = img.width() as usize * P::CHANNEL_COUNT as usize;
+    if stride == 0 {
+        return;
+    }
+    img.par_chunks_mut(stride)
+        .enumerate()
+        .for_each(|(y, row)| f(y as u32, row));
+}
+
+/// 将 src 混合到 RGB 或 RGBA 像素的子像素上
+pub(crate) fn blend_into<S: Primitive>(dst: &mut [S], src: &Rgba<S>)
+where
+    Rgba<S>: Pixel<Subpixel = S>,
+{
+    let alpha = dst.get(3).copied().unwrap_or(S::DEFAULT_MAX_VALUE);
+    let mut p = Rgba([dst[0], dst[1], dst[2], alpha]);
+    p.blend(src);
+    let n = dst.len();
+    dst.copy_from_slice(&p.0[..n]);
+}
+
+/// 以 (x, y) 为左上角将图层按行并行混合到画布的 rows 行，用于照片这类大图层。
+/// 指定 gamma 时在线性光下混合
+pub(crate) fn composite<P, S, T>(
+    canvas: &mut ImageBuffer<P, Vec<S>>,
+    layer: &T,
+    x: i64,
+    y: i64,
+    rows: Range<u32>,
+    gamma: Option<&Gamma<S>>,
+) where
+    P: Pixel<Subpixel = S>,
+    S: Depth,
+    Rgba<S>: Pixel<Subpixel = S>,
+    T: GenericImageView<Pixel = Rgba<S>> + Sync,
+{
+    let (w, h) = layer.dimensions();
+    let n = P::CHANNEL_COUNT as usize;
+    par_rows(canvas, |cy, row| {
+        let ly = cy as i64 - y;
+        if !rows.contains(&cy) || ly < 0 || ly >= h as i64 {
+            return;
+        }
+        for (cx, b) in row.chunks_exact_mut(n).enumerate() {
+            let lx = cx as i64 - x;
+            if lx < 0 || lx >= w as i64 {
+                continue;
+            }
+            let p = layer.get_pixel(lx as u32, ly as u32);
+            match gamma {
+                Some(g) => g.blend(b, &p),
+                None => blend_into(b, &p),
+            }
+        }
+    });
+}
+
 fn open_img<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<DynamicImage> {
     let mut img = image::open(path.as_ref())?;
     if let Ok(v) = get_orientation(path.as_ref()) {
@@ -92,78 +523,269 @@ fn open_img<P: AsRef<std::path::Path>>(path: P) -> anyhow::Result<DynamicImage>
     Ok(img)
 }
 
+/// 保存图像，格式不支持高位深时(如 jpeg)依次降为 16 位、8 位。
+/// 只在降低位深时另建较小的副本
+fn save<P: AsRef<Path>>(img: &DynamicImage, path: P) -> anyhow::Result<()> {
+    if img.color().has_alpha() && !keeps_alpha(path.as_ref()) {
+        return Err(anyhow::Error::msg(format!(
+            "{:?} cannot keep the transparent canvas, use png or webp",
+            path.as_ref()
+        )));
+    }
+    let mut img = Cow::Borrowed(img);
+    loop {
+        match img.save(path.as_ref()) {
+            Err(image::ImageError::Unsupported(e)) => {
+                // 降低位深时保留透明通道
+                let lower = match img.color() {
+                    ColorType::Rgb32F => DynamicImage::ImageRgb16(img.to_rgb16()),
+                    ColorType::Rgba32F => DynamicImage::ImageRgba16(img.to_rgba16()),
+                    ColorType::Rgb16 => DynamicImage::ImageRgb8(img.to_rgb8()),
+                    ColorType::Rgba16 => DynamicImage::ImageRgba8(img.to_rgba8()),
+                    _ => return Err(e.into()),
+                };
+                log::info!("falling back to {:?}", lower.color());
+                img = Cow::Owned(lower);
+            }
+            res => return Ok(res?),
+        }
+    }
+}
+
+/// 背景源：照片或另指定的图片，按取景设置截取，宽高比与 width x height 一致
+fn backdrop<'a>(
+    bg: &config::Background,
+    img: &'a DynamicImage,
+    width: u32,
+    height: u32,
+) -> anyhow::Result<Cow<'a, DynamicImage>> {
+    let src = match bg.image.as_ref() {
+        Some(path) => Cow::Owned(open_img(path)?),
+        None => Cow::Borrowed(img),
+    };
+    Ok(match bg.frame(src.width(), src.height(), width, height) {
+        Some((x, y, w, h)) => Cow::Owned(src.crop_imm(x, y, w, h)),
+        None => src,
+    })
+}
+
 pub fn go(cfg: Config) -> anyhow::Result<()> {
-    let mut img = open_img(cfg.source_file.as_str())?;
+    let img = open_img(cfg.source_file.as_str())?;
+    // 16 位与浮点照片按原位深渲染，避免渐变与模糊背景出现色带
+    match img.color() {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            render::<u16>(&cfg, img)
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => render::<f32>(&cfg, img),
+        _ => render::<u8>(&cfg, img),
+    }
+}
+
+fn render<S: Depth>(cfg: &Config, mut img: DynamicImage) -> anyhow::Result<()>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+    Rgba<S>: Pixel<Subpixel = S>,
+    Rgb<S::Linear>: Pixel<Subpixel = S::Linear>,
+{
+    if cfg.background.mode == config::BackgroundMode::Transparent {
+        // 透明画布不能与白底同时使用，输出格式须支持 alpha，渲染前检查
+        if cfg.white_bg {
+            return Err(anyhow::Error::msg(
+                "white_bg conflicts with the transparent background",
+            ));
+        }
+        if let Some(path) = outputs(cfg).into_iter().find(|p| !keeps_alpha(p)) {
+            return Err(anyhow::Error::msg(format!(
+                "{path:?} cannot keep the transparent canvas, use png or webp"
+            )));
+        }
+    }
     let (bg_width, bg_height) = cfg.size.calc_bg(img.width(), img.height());
-    let bg_img = if cfg.white_bg {
-        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
-            bg_width,
-            bg_height,
-            image::Rgb([255; 3]),
-        ))
-    } else {
-        img.resize_to_fill(bg_width, bg_height, FilterType::Nearest)
+    let spec = Blur {
+        radius: cfg.size.blur_radius as f32,
+        style: cfg.size.blur_style,
+        edge: cfg.size.blur_edge,
+    };
+    let (w, h, budget) = (bg_width, bg_height, cfg.memory_budget);
+    // 边缘延伸依赖照片在画布中的位置，缩放照片后再生成；透明画布不需要背景
+    let bg_img = match cfg.background.mode {
+        _ if cfg.white_bg => Some(ImageBuffer::from_pixel(
+            w,
+            h,
+            Rgb([S::DEFAULT_MAX_VALUE; 3]),
+        )),
+        config::BackgroundMode::Extend { .. } | config::BackgroundMode::Transparent => None,
+        config::BackgroundMode::Procedural { pattern, seed } => {
+            let colors = effects::stylize::colors(&img, 5);
+            Some(procedural::render::<S>(pattern, seed, w, h, &colors))
+        }
+        config::BackgroundMode::Mosaic { cell } => {
+            let src = backdrop(&cfg.background, &img, w, h)?;
+            Some(effects::stylize::mosaic::<S>(&src, w, h, cell))
+        }
+        mode => {
+            let src = backdrop(&cfg.background, &img, w, h)?;
+            let mut bg = blur_to_fill::<S>(&src, w, h, spec, budget, cfg.linear_light);
+            effects::stylize::apply(&mut bg, &mode, &img);
+            Some(bg)
+        }
     };
     if !cfg.size.aspect_ratio {
-        let r = 1.0 - cfg.size.padding * 2.0;
-        let width = bg_img.width() as f64 * r;
-        let height = bg_img.height() as f64 * r;
-        img = img.resize(width as u32, height as u32, FilterType::Nearest);
+        let (_, _, width, height) = cfg.size.padding.inner(w, h);
+        img = resize_nearest(img, width, height);
+    }
+    let (dist_h, dist_v) = cfg.size.position(w, h, img.dimensions());
+    let mut bg_img = match (bg_img, cfg.background.mode) {
+        (Some(bg), _) => Some(bg),
+        (None, config::BackgroundMode::Extend { spread }) => Some(effects::extend::apply::<S>(
+            &img, w, h, dist_h, dist_v, spread,
+        )),
+        (None, _) => None,
+    };
+    if let Some(bg) = bg_img.as_mut() {
+        if !cfg.white_bg {
+            effects::tone::apply(bg, &cfg.tone);
+        }
+        if let Some(grain) = cfg.grain.as_ref() {
+            effects::grain::apply(bg, grain);
+        }
     }
-    let dist_v = (bg_img.height() - img.height()) / 2;
-    let dist_h = (bg_img.width() - img.width()) / 2;
+    if let Some(stamp) = cfg.date_stamp.as_ref() {
+        match get_date(cfg.source_file.as_str()) {
+            Ok(dt) => {
+                let text = effects::date_stamp::format(&dt, stamp.format.as_str());
+                effects::date_stamp::apply(&mut img, stamp, text.as_str());
+            }
+            Err(e) => log::warn!("date stamp skipped: {e:?}"),
+        }
+    }
+    let caption_region = cfg.caption.as_ref().map(|c| match c.side {
+        config::Side::Left => text::Region {
+            x: 0,
+            y: dist_v as i32,
+            width: dist_h,
+            height: img.height(),
+        },
+        config::Side::Right => text::Region {
+            x: (dist_h + img.width()) as i32,
+            y: dist_v as i32,
+            width: w - dist_h - img.width(),
+            height: img.height(),
+        },
+        config::Side::Top => text::Region {
+            x: dist_h as i32,
+            y: 0,
+            width: img.width(),
+            height: dist_v,
+        },
+        config::Side::Bottom => text::Region {
+            x: dist_h as i32,
+            y: (dist_v + img.height()) as i32,
+            width: img.width(),
+            height: h - dist_v - img.height(),
+        },
+    });
+
+    let (x, y) = (dist_h as i64, dist_v as i64);
+    // DynamicImage 以 RGBA 读写画布，供标题绘制
+    let mut bg_img = match bg_img {
+        Some(mut bg) => {
+            place(cfg, &mut bg, img, x, y);
+            S::dynamic(bg)
+        }
+        None => {
+            // 透明画布保留照片自身的透明区域，阴影按 alpha 叠加
+            let mut canvas = ImageBuffer::<Rgba<S>, _>::new(w, h);
+            place(cfg, &mut canvas, img, x, y);
+            S::dynamic_rgba(canvas)
+        }
+    };
+    if let (Some(caption), Some(region)) = (cfg.caption.as_ref(), caption_region) {
+        let mut book = text::FontBook::new();
+        let fonts = book.resolve(caption.spans.as_slice(), cfg.font.as_ref())?;
+        text::draw(&mut bg_img, fonts.as_slice(), caption, region);
+    }
+    for path in outputs(cfg) {
+        log::info!("saving to {:?}", path.as_path());
+        save(&bg_img, path.as_path())?;
+    }
+    Ok(())
+}
+
+/// 输出路径。与原图相同或未指定时，另存一份加 bmps_ 前缀的副本
+fn outputs(cfg: &Config) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    if cfg.source_file == cfg.dest_file || cfg.dest_file.is_empty() {
+        let mut pb = PathBuf::from(cfg.source_file.as_str());
+        let name = format!(
+            "bmps_{}",
+            pb.file_name().unwrap_or_default().to_string_lossy()
+        );
+        pb.pop();
+        pb.push(name);
+        paths.push(pb);
+    }
+    paths.push(PathBuf::from(cfg.dest_file.as_str()));
+    paths
+}
 
-    let mut bg_img = blur(cfg.size.blur_radius as f32, bg_img);
-    let rounded = effects::round::Rounded::new(&img, cfg.size.round_radius);
+/// 格式能否保存透明通道。jpeg 等格式会直接丢弃 alpha
+fn keeps_alpha(path: &Path) -> bool {
+    matches!(
+        ImageFormat::from_path(path),
+        Ok(ImageFormat::Png
+            | ImageFormat::WebP
+            | ImageFormat::Tiff
+            | ImageFormat::Tga
+            | ImageFormat::Ico
+            | ImageFormat::Avif
+            | ImageFormat::OpenExr
+            | ImageFormat::Qoi)
+    )
+}
 
+/// 在 RGB 或 RGBA 画布的 (x, y) 处绘制阴影与圆角照片
+fn place<P, S>(cfg: &Config, canvas: &mut ImageBuffer<P, Vec<S>>, img: DynamicImage, x: i64, y: i64)
+where
+    P: Pixel<Subpixel = S>,
+    S: Depth,
+    Rgb<S>: Pixel<Subpixel = S>,
+    Rgba<S>: Pixel<Subpixel = S>,
+{
     //  draw shadow
     let shadow = effects::shadow::Builder::new()
         .offset(cfg.size.shadow_offset_x, cfg.size.shadow_offset_y)
         .blur_radius(cfg.size.shadow)
         .color([0, 0, 0, 200])
+        .linear(cfg.linear_light)
         .build();
     let draw_shadow_cost = Instant::now();
-    let (img, dx, dy) = shadow.apply(&rounded);
+    let radius = cfg.size.round_radius;
+    if img.color().has_alpha() {
+        // 照片自带透明区域，形状任意，只能模糊 alpha 图层
+        let photo = S::rgba(img);
+        let rounded = effects::round::Rounded::new(&photo, radius);
+        shadow.draw(canvas, &rounded, x, y, cfg.memory_budget);
+    } else {
+        let (w, h) = img.dimensions();
+        shadow.draw_rounded(canvas, x as i32, y as i32, w, h, radius);
+        let photo = S::rgb(img);
+        let rounded = effects::round::Rounded::new(&photo, radius);
+        let rows = 0..canvas.height();
+        let gamma = cfg.linear_light.then(S::gamma);
+        composite(canvas, &rounded, x, y, rows, gamma);
+    }
     log::info!(
         "draw_shadow_cost: {}ms",
         draw_shadow_cost.elapsed().as_millis()
     );
-    // draw shadowed content to background
-    img.enumerate_pixels().for_each(|(x, y, f)| {
-        let x = x + dist_h;
-        let y = y + dist_v;
-        if x < dx || y < dy {
-            return;
-        }
-        let x = x - dx;
-        let y = y - dy;
-        if x < bg_img.width() && y < bg_img.height() {
-            let mut b = bg_img.get_pixel(x, y);
-            b.blend(f);
-            bg_img.put_pixel(x, y, b);
-        }
-    });
-    if cfg.source_file == cfg.dest_file || cfg.dest_file.is_empty() {
-        let mut pb = std::path::PathBuf::from(cfg.source_file.as_str());
-        let name = format!(
-            "bmps_{}",
-            pb.file_name().unwrap_or_default().to_string_lossy()
-        );
-        pb.pop();
-        pb.push(name);
-        log::info!("saving to {:?}", pb.as_path());
-        bg_img.save(pb.as_path())?;
-    }
-    log::info!("saving to {}", cfg.dest_file);
-    bg_img.save(cfg.dest_file.as_str())?;
-    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use std::time::Instant;
 
-    use image::{Pixel, Rgba, RgbaImage};
+    use image::{imageops::FilterType, Pixel, Rgba, RgbaImage};
 
     use super::*;
 
@@ -181,6 +803,13 @@ mod tests {
             font: None,
             size: Default::default(),
             white_bg: false,
+            caption: None,
+            date_stamp: None,
+            memory_budget: None,
+            linear_light: false,
+            tone: Default::default(),
+            grain: None,
+            background: Default::default(),
         };
         go(cfg.clone()).unwrap();
         std::mem::swap(&mut cfg.size.width, &mut cfg.size.height);
@@ -189,6 +818,348 @@ mod tests {
         log::info!("cost {}ms", s.elapsed().as_millis());
     }
 
+    #[test]
+    fn tiled() {
+        let _ = env_logger::try_init();
+        // 带透明区域的照片走通用阴影路径
+        let mut photo = open_img("./hello.jpg")
+            .unwrap()
+            .resize(480, 480, FilterType::Triangle)
+            .into_rgba8();
+        photo
+            .enumerate_pixels_mut()
+            .filter(|(x, y, _)| (x / 40 + y / 40) % 5 == 0)
+            .for_each(|(_, _, p)| p.0[3] = 0);
+        photo.save("./output_tiled_src.png").unwrap();
+        // 线性光与其他模糊风格也逐条解码、模糊
+        let variants = [
+            (false, config::BlurStyle::Gaussian),
+            (true, config::BlurStyle::Gaussian),
+            (true, config::BlurStyle::Bokeh { highlight: 4.0 }),
+        ];
+        for source in ["./hello.jpg", "./output_tiled_src.png"] {
+            for (linear_light, blur_style) in variants {
+                let mut cfg = Config {
+                    source_file: source.to_owned(),
+                    dest_file: "./output_full.png".to_owned(),
+                    font: None,
+                    size: config::Size {
+                        width: 600,
+                        height: 800,
+                        blur_radius: 20,
+                        blur_style,
+                        ..Default::default()
+                    },
+                    white_bg: false,
+                    caption: None,
+                    date_stamp: None,
+                    memory_budget: None,
+                    linear_light,
+                    tone: Default::default(),
+                    grain: None,
+                    background: Default::default(),
+                };
+                go(cfg.clone()).unwrap();
+                cfg.dest_file = "./output_tiled.png".to_owned();
+                cfg.memory_budget = Some(2 << 20);
+                go(cfg).unwrap();
+                let full = image::open("./output_full.png").unwrap().into_rgb8();
+                let tiled = image::open("./output_tiled.png").unwrap().into_rgb8();
+                assert!(full == tiled, "{source} {linear_light} {blur_style:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn deep() {
+        let _ = env_logger::try_init();
+        // 缓慢的 16 位渐变，8 位下只剩少数几级
+        let (w, h) = (400, 300);
+        let grad = image::ImageBuffer::from_fn(w, h, |x, y| {
+            image::Rgb([(x * 40) as u16, (y * 30) as u16, 20000])
+        });
+        DynamicImage::ImageRgb16(grad)
+            .save("./output_deep_src.png")
+            .unwrap();
+        let mut cfg = Config {
+            source_file: "./output_deep_src.png".to_owned(),
+            dest_file: "./output_deep.png".to_owned(),
+            font: None,
+            size: config::Size {
+                width: 600,
+                height: 400,
+                blur_radius: 20,
+                ..Default::default()
+            },
+            white_bg: false,
+            caption: None,
+            date_stamp: None,
+            memory_budget: None,
+            linear_light: false,
+            tone: Default::default(),
+            grain: None,
+            background: Default::default(),
+        };
+        go(cfg.clone()).unwrap();
+        let out = image::open("./output_deep.png").unwrap();
+        assert_eq!(out.color(), image::ColorType::Rgb16);
+        let out = out.into_rgb16();
+        let mut reds: Vec<u16> = (0..out.width()).map(|x| out.get_pixel(x, 5)[0]).collect();
+        reds.dedup();
+        assert!(reds.len() > 256, "{} levels", reds.len());
+        // jpeg 不支持 16 位，降为 8 位保存
+        cfg.dest_file = "./output_deep.jpg".to_owned();
+        go(cfg).unwrap();
+        let out = image::open("./output_deep.jpg").unwrap();
+        assert_eq!(out.color(), image::ColorType::Rgb8);
+    }
+
+    #[test]
+    fn linear_light() {
+        let _ = env_logger::try_init();
+        let mut cfg = Config {
+            source_file: "./hello.jpg".to_owned(),
+            dest_file: "./output_srgb.png".to_owned(),
+            font: None,
+            size: config::Size {
+                width: 600,
+                height: 400,
+                ..Default::default()
+            },
+            white_bg: true,
+            caption: None,
+            date_stamp: None,
+            memory_budget: None,
+            linear_light: false,
+            tone: Default::default(),
+            grain: None,
+            background: Default::default(),
+        };
+        go(cfg.clone()).unwrap();
+        cfg.dest_file = "./output_linear.png".to_owned();
+        cfg.linear_light = true;
+        go(cfg).unwrap();
+        let srgb = image::open("./output_srgb.png").unwrap().into_rgb8();
+        let linear = image::open("./output_linear.png").unwrap().into_rgb8();
+        // 白底上的黑色阴影在线性光下混合更浅，照片本身不透明，保持不变
+        let mut lighter = 0;
+        for (a, b) in srgb.pixels().zip(linear.pixels()) {
+            for i in 0..3 {
+                assert!(b[i] >= a[i].saturating_sub(1), "{a:?} {b:?}");
+                lighter += (b[i] > a[i].saturating_add(1)) as usize;
+            }
+        }
+        assert!(lighter > 0);
+    }
+
+    #[test]
+    fn backdrop() {
+        let mut bg = config::Background::default();
+        assert_eq!(bg.frame(4000, 3000, 1920, 1080), None);
+        // 放大两倍并靠右上取景，超出部分靠边
+        bg.zoom = 2.0;
+        bg.focus_x = 1.0;
+        bg.focus_y = 0.0;
+        assert_eq!(
+            bg.frame(4000, 3000, 1920, 1080),
+            Some((2000, 0, 2000, 1125))
+        );
+        bg.zoom = 0.5;
+        bg.focus_x = 0.25;
+        assert_eq!(bg.frame(1000, 1000, 800, 400), Some((0, 0, 1000, 500)));
+        // 另指定的背景图
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(300, 200, Rgb([0; 3])));
+        image::RgbImage::from_pixel(64, 64, Rgb([200, 30, 30]))
+            .save("./output_backdrop_src.png")
+            .unwrap();
+        bg.image = Some("./output_backdrop_src.png".to_owned());
+        let src = super::backdrop(&bg, &img, 800, 400).unwrap();
+        assert_eq!(src.dimensions(), (64, 32));
+        assert_eq!(src.to_rgb8().get_pixel(0, 0).0, [200, 30, 30]);
+        bg.image = None;
+        let src = super::backdrop(&bg, &img, 800, 400).unwrap();
+        assert_eq!(src.dimensions(), (300, 150));
+    }
+
+    #[test]
+    fn position() {
+        let mut size = config::Size::default();
+        assert_eq!(size.position(1920, 1080, (1000, 864)), (460, 108));
+        // 靠右下时保留 padding，左侧留白加宽
+        size.align_x = config::Align::End;
+        size.align_y = config::Align::End;
+        assert_eq!(size.position(1920, 1080, (1000, 864)), (728, 108));
+        size.align_x = config::Align::Start;
+        assert_eq!(size.position(1920, 1080, (1000, 864)), (192, 108));
+        // 偏移按像素或画布比例，超出画布时靠边
+        size.offset_x = config::Length::Ratio(-0.05);
+        size.offset_y = config::Length::Pixels(-40);
+        assert_eq!(size.position(1920, 1080, (1000, 864)), (96, 68));
+        size.offset_y = config::Length::Pixels(500);
+        assert_eq!(size.position(1920, 1080, (1000, 864)), (96, 216));
+    }
+
+    #[test]
+    fn padding() {
+        // 左右各 50 像素，上 5%、下 20%
+        let padding = config::Padding {
+            top: config::Length::Ratio(0.05),
+            right: config::Length::Pixels(50),
+            bottom: config::Length::Ratio(0.2),
+            left: config::Length::Pixels(50),
+        };
+        let mut size = config::Size {
+            width: 1000,
+            height: 1000,
+            padding,
+            ..Default::default()
+        };
+        assert_eq!(padding.inner(1000, 1000), (50, 50, 900, 750));
+        assert_eq!(size.position(1000, 1000, (600, 750)), (200, 50));
+        // 宽高比模式下画布刚好容纳照片与留白
+        size.width = 4;
+        size.height = 5;
+        size.aspect_ratio = true;
+        assert_eq!(size.calc_bg(900, 900), (1000, 1250));
+        assert_eq!(padding.inner(1000, 1250), (50, 63, 900, 937));
+        assert_eq!(size.calc_bg(600, 1200), (1280, 1600));
+        assert_eq!(padding.inner(1280, 1600), (50, 80, 1180, 1200));
+        // 均匀留白与原先的计算一致
+        size.padding = config::Padding::uniform(0.25);
+        assert_eq!(size.calc_bg(500, 500), (1000, 1250));
+        // 边长除不尽时向上取整：1001 / 0.8 = 1251.25
+        size.width = 1;
+        size.height = 1;
+        size.padding = config::Padding::uniform(0.1);
+        assert_eq!(size.calc_bg(1001, 1001), (1252, 1252));
+        assert_eq!(size.padding.inner(1252, 1252), (125, 125, 1002, 1002));
+        // 1004 / 0.8 刚好为 1255，但两侧 125.5 各进位为 126，须再放大一个像素
+        assert_eq!(size.calc_bg(1004, 1004), (1256, 1256));
+        // 16:9 下照片总在留白以内
+        size.width = 16;
+        size.height = 9;
+        for len in 990..1010 {
+            for photo in [(len, 777), (777, len), (len, len)] {
+                let (w, h) = size.calc_bg(photo.0, photo.1);
+                assert!(h == (w * 9).div_ceil(16) || w == (h * 16).div_ceil(9));
+                let (x, y) = size.position(w, h, photo);
+                let (ix, iy, iw, ih) = size.padding.inner(w, h);
+                assert!(x >= ix && y >= iy, "{photo:?}");
+                assert!(
+                    x + photo.0 <= ix + iw && y + photo.1 <= iy + ih,
+                    "{photo:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transparent() {
+        let _ = env_logger::try_init();
+        // 透明底上的圆形 logo
+        let logo = RgbaImage::from_fn(200, 200, |x, y| {
+            let d = (x as f32 - 99.5).hypot(y as f32 - 99.5);
+            Rgba([200, 30, 30, if d < 100.0 { 255 } else { 0 }])
+        });
+        logo.save("./output_transparent_src.png").unwrap();
+        let mut cfg = Config {
+            source_file: "./output_transparent_src.png".to_owned(),
+            dest_file: "./output_transparent.png".to_owned(),
+            font: None,
+            size: config::Size {
+                width: 400,
+                height: 400,
+                shadow: 0,
+                ..Default::default()
+            },
+            white_bg: false,
+            caption: None,
+            date_stamp: None,
+            memory_budget: None,
+            linear_light: false,
+            tone: Default::default(),
+            grain: None,
+            background: config::Background {
+                mode: config::BackgroundMode::Transparent,
+                ..Default::default()
+            },
+        };
+        go(cfg.clone()).unwrap();
+        let out = image::open("./output_transparent.png").unwrap();
+        assert!(out.color().has_alpha());
+        let out = out.into_rgba8();
+        assert_eq!(out.dimensions(), (400, 400));
+        assert_eq!(out.get_pixel(0, 0)[3], 0);
+        // logo 本身的透明区域保持透明
+        assert_eq!(out.get_pixel(42, 42)[3], 0);
+        assert_eq!(out.get_pixel(200, 200).0, [200, 30, 30, 255]);
+        // 不透明照片的阴影为半透明
+        cfg.source_file = "./hello.jpg".to_owned();
+        cfg.size.shadow = 40;
+        go(cfg.clone()).unwrap();
+        let out = image::open("./output_transparent.png")
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(out.get_pixel(0, 0)[3], 0);
+        assert!(out.pixels().any(|p| p[3] > 0 && p[3] < 255));
+        assert!(out.pixels().any(|p| p[3] == 255));
+        // 不能与白底同时使用，也不能存为丢弃 alpha 的格式
+        cfg.white_bg = true;
+        assert!(go(cfg.clone()).is_err());
+        cfg.white_bg = false;
+        cfg.dest_file = "./output_transparent.jpg".to_owned();
+        assert!(go(cfg.clone()).is_err());
+        cfg.dest_file = "./output_transparent.webp".to_owned();
+        go(cfg).unwrap();
+        let rgba = DynamicImage::ImageRgba8(RgbaImage::new(4, 4));
+        assert!(save(&rgba, "./output_transparent.jpg").is_err());
+        save(&rgba, "./output_transparent.png").unwrap();
+    }
+
+    #[test]
+    fn fast_blur() {
+        let _ = env_logger::try_init();
+        let img = open_img("./hello.jpg").unwrap();
+        // 奇数宽高经缩小、放大后尺寸不变
+        for (radius, w, h) in [
+            (20.0, 1920, 1080),
+            (50.0, 1920, 1080),
+            (120.0, 1920, 1080),
+            (50.0, 1001, 777),
+        ] {
+            let mut full = fill::<u8>(&img, w, h);
+            let spec = Blur {
+                radius,
+                ..Default::default()
+            };
+            blur(spec, &mut full, None, false);
+            let fast = blur_to_fill(&img, w, h, spec, None, false);
+            assert_eq!(fast.dimensions(), (w, h));
+            let mut max = 0;
+            let mut sum = 0u64;
+            for (a, b) in full.pixels().zip(fast.pixels()) {
+                for i in 0..3 {
+                    let d = a[i].abs_diff(b[i]);
+                    max = max.max(d);
+                    sum += d as u64;
+                }
+            }
+            let mean = sum as f64 / (full.len() as f64);
+            log::info!("radius {radius} {w}x{h}: max error {max}, mean {mean:.3}");
+            assert!(max <= FAST_BLUR_MAX_ERROR);
+        }
+        // 其他风格不缩小，与全尺寸模糊逐像素一致
+        let spec = Blur {
+            radius: 50.0,
+            style: config::BlurStyle::Bokeh { highlight: 2.0 },
+            ..Default::default()
+        };
+        assert_eq!(downscale_factor(spec), 1);
+        let mut full = fill::<u8>(&img, 640, 480);
+        blur(spec, &mut full, None, false);
+        assert_eq!(blur_to_fill(&img, 640, 480, spec, None, false), full);
+    }
+
     #[test]
     fn shadow() {
         let _ = env_logger::try_init();
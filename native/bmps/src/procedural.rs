@@ -0,0 +1,264 @@
+//! 程序生成的背景：分形、噪声与网格渐变。
+//! 坐标按画布短边归一化，同一 seed 在任意分辨率下得到相同的图案
+use image::{ImageBuffer, Pixel, Rgb};
+
+use crate::config::Pattern;
+use crate::depth::Depth;
+use crate::effects::grain::hash;
+
+// 分形中心的候选点，均位于 Mandelbrot 集边界附近
+const FRACTAL_CENTERS: [(f64, f64); 4] = [
+    (-0.743_643_9, 0.131_825_9),
+    (-0.101_1, 0.956_3),
+    (0.282, 0.010),
+    (-1.250_66, 0.020_12),
+];
+const FRACTAL_LIMIT: usize = 400;
+// 分形逐点迭代代价高，短边超过此值时在缩小的网格上计算，再双线性放大
+const FRACTAL_SIDE: u32 = 720;
+
+/// 生成 width x height 的背景，colors 为由暗到亮的配色
+pub fn render<S: Depth>(
+    pattern: Pattern,
+    seed: u64,
+    width: u32,
+    height: u32,
+    colors: &[[u8; 3]],
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let k = width.min(height).div_ceil(FRACTAL_SIDE);
+    if pattern != Pattern::Fractal || k <= 1 {
+        return grid(pattern, seed, width, height, colors);
+    }
+    let small = grid::<f32>(pattern, seed, width.div_ceil(k), height.div_ceil(k), colors);
+    let max = S::DEFAULT_MAX_VALUE.into_f64();
+    crate::upscale::<f32, S, _>(&small, width, height, |v| S::from_f64(v as f64 * max))
+}
+
+/// 逐像素采样图案
+fn grid<S: Depth>(
+    pattern: Pattern,
+    seed: u64,
+    width: u32,
+    height: u32,
+    colors: &[[u8; 3]],
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    Rgb<S>: Pixel<Subpixel = S>,
+{
+    let mut bg = ImageBuffer::new(width, height);
+    if colors.is_empty() {
+        return bg;
+    }
+    let max = S::DEFAULT_MAX_VALUE.into_f64();
+    let unit = (width.min(height).max(1)) as f64;
+    let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+    let mut rng = Rng(seed);
+    let sample: Box<dyn Fn(f64, f64) -> [f64; 3] + Sync> = match pattern {
+        Pattern::Fractal => {
+            let (re, im) = FRACTAL_CENTERS[(rng.next() * 4.0) as usize % 4];
+            let scale = 0.01 * 20f64.powf(rng.next());
+            let (sin, cos) = (rng.next() * std::f64::consts::TAU).sin_cos();
+            let phase = rng.next();
+            Box::new(move |u, v| {
+                let (u, v) = (u * cos - v * sin, u * sin + v * cos);
+                match escape_time(re + u * scale, im + v * scale) {
+                    // 取对数使深处的细节也有色彩变化，来回往复避免色带跳变
+                    Some(mu) => ramp(colors, fold((mu.max(1.0)).ln() * 0.6 + phase)),
+                    None => ramp(colors, 0.0),
+                }
+            })
+        }
+        Pattern::Noise => {
+            let seed = hash(seed);
+            let (ox, oy) = (rng.next() * 100.0, rng.next() * 100.0);
+            Box::new(move |u, v| {
+                let (u, v) = (u * 2.5 + ox, v * 2.5 + oy);
+                // 以一层噪声扭曲坐标，图案更像流动的烟雾
+                let w = fbm(seed ^ 1, u, v);
+                let n = fbm(seed, u + 1.5 * w, v - 1.5 * w);
+                ramp(colors, (n * 0.8 + 0.5).clamp(0.0, 1.0))
+            })
+        }
+        Pattern::Mesh => {
+            let points: Vec<(f64, f64, [f64; 3])> = (0..colors.len().max(4))
+                .map(|i| {
+                    let u = (rng.next() - 0.5) * width as f64 / unit;
+                    let v = (rng.next() - 0.5) * height as f64 / unit;
+                    let c = colors[i % colors.len()];
+                    (u, v, c.map(|c| c as f64 / 255.0))
+                })
+                .collect();
+            Box::new(move |u, v| {
+                let mut sum = [0f64; 3];
+                let mut total = 0.0;
+                for (pu, pv, c) in points.iter() {
+                    let d = (u - pu).powi(2) + (v - pv).powi(2);
+                    let w = (-d / 0.04).exp() + 1e-12;
+                    for i in 0..3 {
+                        sum[i] += c[i] * w;
+                    }
+                    total += w;
+                }
+                sum.map(|s| s / total)
+            })
+        }
+    };
+    crate::par_rows(&mut bg, |y, row| {
+        let v = (y as f64 + 0.5 - cy) / unit;
+        for (x, p) in row.chunks_exact_mut(3).enumerate() {
+            let u = (x as f64 + 0.5 - cx) / unit;
+            let c = sample(u, v);
+            for i in 0..3 {
+                p[i] = S::from_f64(c[i] * max);
+            }
+        }
+    });
+    bg
+}
+
+/// Mandelbrot 迭代 z = z^2 + c 逃逸所需的连续迭代次数，c 在集合内时为 None。
+/// 逃逸半径取 16 使小数部分准确，颜色平滑过渡而不成色带
+fn escape_time(re: f64, im: f64) -> Option<f64> {
+    let (mut zr, mut zi) = (0.0f64, 0.0f64);
+    for i in 0..FRACTAL_LIMIT {
+        let norm = zr * zr + zi * zi;
+        if norm > 256.0 {
+            return Some(i as f64 + 2.0 - norm.ln().ln() / std::f64::consts::LN_2);
+        }
+        (zr, zi) = (zr * zr - zi * zi + re, 2.0 * zr * zi + im);
+    }
+    None
+}
+
+/// 由 seed 决定的 [0, 1) 随机数序列
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> f64 {
+        self.0 = hash(self.0);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// 在 colors 之间线性插值，t 取值 [0, 1]，结果取值 [0, 1]
+fn ramp(colors: &[[u8; 3]], t: f64) -> [f64; 3] {
+    let f = t.clamp(0.0, 1.0) * (colors.len() - 1) as f64;
+    let i = (f.floor() as usize).min(colors.len().saturating_sub(2));
+    let (a, b) = (colors[i], colors[(i + 1).min(colors.len() - 1)]);
+    let t = f - i as f64;
+    [0, 1, 2].map(|c| (a[c] as f64 + (b[c] as f64 - a[c] as f64) * t) / 255.0)
+}
+
+/// 将任意实数往复折叠到 [0, 1]
+fn fold(t: f64) -> f64 {
+    let t = t.rem_euclid(2.0);
+    if t > 1.0 {
+        2.0 - t
+    } else {
+        t
+    }
+}
+
+/// 四层 Perlin 噪声叠加，取值约 [-0.7, 0.7]
+fn fbm(seed: u64, u: f64, v: f64) -> f64 {
+    let (mut sum, mut amp, mut freq) = (0.0, 0.5, 1.0);
+    for octave in 0..4 {
+        sum += amp * perlin(seed.wrapping_add(octave), u * freq, v * freq);
+        amp *= 0.5;
+        freq *= 2.0;
+    }
+    sum
+}
+
+/// 二维 Perlin 噪声，格点梯度方向由坐标哈希得到
+fn perlin(seed: u64, u: f64, v: f64) -> f64 {
+    let (x0, y0) = (u.floor(), v.floor());
+    let (fx, fy) = (u - x0, v - y0);
+    let (x0, y0) = (x0 as i64, y0 as i64);
+    let grad = |x: i64, y: i64, dx: f64, dy: f64| {
+        let h = hash(seed ^ hash(x as u64 ^ hash(y as u64)));
+        let (sin, cos) = ((h >> 11) as f64 / (1u64 << 53) as f64 * std::f64::consts::TAU).sin_cos();
+        cos * dx + sin * dy
+    };
+    let fade = |t: f64| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let (sx, sy) = (fade(fx), fade(fy));
+    let a = grad(x0, y0, fx, fy);
+    let b = grad(x0 + 1, y0, fx - 1.0, fy);
+    let c = grad(x0, y0 + 1, fx, fy - 1.0);
+    let d = grad(x0 + 1, y0 + 1, fx - 1.0, fy - 1.0);
+    let top = a + (b - a) * sx;
+    let bottom = c + (d - c) * sx;
+    top + (bottom - top) * sy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    const COLORS: [[u8; 3]; 4] = [[20, 20, 40], [90, 40, 80], [200, 120, 90], [250, 230, 200]];
+    const PATTERNS: [Pattern; 3] = [Pattern::Fractal, Pattern::Noise, Pattern::Mesh];
+
+    #[test]
+    fn seeded() {
+        for pattern in PATTERNS {
+            let a: RgbImage = render(pattern, 1, 80, 60, &COLORS);
+            let b: RgbImage = render(pattern, 1, 80, 60, &COLORS);
+            let c: RgbImage = render(pattern, 2, 80, 60, &COLORS);
+            assert!(a == b, "{pattern:?}");
+            assert!(a != c, "{pattern:?}");
+            assert!(a.pixels().any(|p| p != a.get_pixel(0, 0)), "{pattern:?}");
+        }
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!(escape_time(0.0, 0.0), None);
+        // 越靠近集合迭代越久，相邻点的迭代次数连续变化
+        let a = escape_time(1.0, 0.0).unwrap();
+        let b = escape_time(1.01, 0.0).unwrap();
+        assert!(a > b && a - b < 0.2);
+    }
+
+    #[test]
+    fn fractal_upscaled() {
+        // 短边超过 FRACTAL_SIDE 时在缩小的网格上计算，与逐像素计算的图案一致
+        let (w, h) = (FRACTAL_SIDE * 2, FRACTAL_SIDE * 3 / 2);
+        let big: RgbImage = render(Pattern::Fractal, 3, w, h, &COLORS);
+        let small: RgbImage = grid(Pattern::Fractal, 3, w / 2, h / 2, &COLORS);
+        let mut sum = 0u64;
+        for (x, y, p) in small.enumerate_pixels() {
+            let b = big.get_pixel(x * 2, y * 2);
+            for c in 0..3 {
+                sum += p[c].abs_diff(b[c]) as u64;
+            }
+        }
+        let mean = sum as f64 / small.len() as f64;
+        assert!(mean < 8.0, "{mean}");
+    }
+
+    #[test]
+    fn resolution() {
+        // 放大一倍后，对应位置的颜色基本一致
+        for pattern in [Pattern::Noise, Pattern::Mesh] {
+            let small: RgbImage = render(pattern, 5, 200, 150, &COLORS);
+            let big: RgbImage = render(pattern, 5, 400, 300, &COLORS);
+            for (x, y, p) in small.enumerate_pixels() {
+                let mut q = [0u32; 3];
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let b = big.get_pixel(x * 2 + dx, y * 2 + dy);
+                    for c in 0..3 {
+                        q[c] += b[c] as u32;
+                    }
+                }
+                for c in 0..3 {
+                    let diff = (q[c] as f32 / 4.0 - p[c] as f32).abs();
+                    assert!(diff < 8.0, "{pattern:?} ({x}, {y}) {p:?} {q:?}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+//! 高斯以外的背景模糊风格：圆盘(焦外光斑)、运动、放射。
+//! 均作用于 RGB 图像，窗口超出图像边缘时按 Edge 取值
+use rayon::prelude::*;
+
+use crate::blur::{self, Edge};
+use crate::config::BlurStyle;
+use crate::depth::Depth;
+
+/// 按 style 原地模糊，data 为 width x height 个 RGB 像素，radius 为模糊半径(像素数)。
+/// 高斯模糊见 crate::blur，此处不处理
+pub fn apply<S: Depth>(
+    style: &BlurStyle,
+    data: &mut [S],
+    width: usize,
+    height: usize,
+    radius: f32,
+    edge: Edge,
+) {
+    apply_banded(style, data, width, height, radius, edge, None)
+}
+
+/// 分条原地模糊，每条连同上下 halo 行一同计算，结果与 apply 一致。
+/// 条高按 budget（字节）确定，budget 为 None 时整幅计算。
+/// Wrap 需要对侧的行，总是整幅计算
+pub fn apply_banded<S: Depth>(
+    style: &BlurStyle,
+    data: &mut [S],
+    width: usize,
+    height: usize,
+    radius: f32,
+    edge: Edge,
+    budget: Option<usize>,
+) {
+    assert_eq!(data.len(), width * height * 3);
+    if radius < 0.5 || data.is_empty() {
+        return;
+    }
+    let stride = width * 3;
+    let row_bytes = stride * std::mem::size_of::<S>() + scratch(style, width, radius);
+    let budget = budget.filter(|_| edge != Edge::Wrap);
+    let halo = halo(style, radius);
+    let rows = blur::band_rows(budget, row_bytes, halo, height);
+    blur::for_each_band(data, stride, rows, halo, |ext, start, dst, y0| {
+        let src = Src {
+            data: ext,
+            start,
+            width,
+            height,
+            edge,
+        };
+        band(style, &src, dst, y0, radius);
+    });
+}
+
+/// 纵向取样超出本行的范围（行数），分条计算时每条上下需多取这么多行。
+/// 双线性插值多取一行，另留一行给浮点误差
+pub(crate) fn halo(style: &BlurStyle, radius: f32) -> usize {
+    match *style {
+        BlurStyle::Gaussian => 0,
+        BlurStyle::Bokeh { .. } => radius.round() as usize,
+        BlurStyle::Motion { angle } => {
+            let dy = radius.round() * angle.to_radians().sin().abs();
+            dy.ceil() as usize + 2
+        }
+        // 拖影最长 2 * radius，以像素为中心前后各一半
+        BlurStyle::Zoom { .. } => radius.ceil() as usize + 2,
+    }
+}
+
+/// 每行除条带副本外所需的缓冲（字节）：圆盘模糊的前缀和
+pub(crate) fn scratch(style: &BlurStyle, width: usize, radius: f32) -> usize {
+    match *style {
+        BlurStyle::Bokeh { .. } => {
+            let r = radius.round() as usize;
+            (width + 2 * r + 1) * 4 * std::mem::size_of::<f64>()
+        }
+        _ => 0,
+    }
+}
+
+/// 模糊前的若干行，从整幅的第 start 行开始。
+/// 行号按整幅计，超出图像的行按 edge 映射，须落在已取的行内
+pub(crate) struct Src<'a, S> {
+    pub(crate) data: &'a [S],
+    pub(crate) start: usize,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) edge: Edge,
+}
+
+impl<S> Src<'_, S> {
+    /// 整幅第 y 行在 data 中的行号
+    fn local(&self, y: isize) -> usize {
+        self.edge.index(y, self.height) - self.start
+    }
+}
+
+/// 模糊一条，dst 为整幅第 y0 行起的若干行
+pub(crate) fn band<S: Depth>(
+    style: &BlurStyle,
+    src: &Src<S>,
+    dst: &mut [S],
+    y0: usize,
+    radius: f32,
+) {
+    let (width, height) = (src.width, src.height);
+    match *style {
+        BlurStyle::Gaussian => {}
+        BlurStyle::Bokeh { highlight } => bokeh(src, dst, y0, radius, highlight),
+        BlurStyle::Motion { angle } => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            // 图像 y 轴向下，角度按逆时针计
+            let n = radius.round() as i32;
+            sweep(src, dst, y0, |x, y| {
+                (-n..=n).map(move |t| (x + t as f32 * cos, y - t as f32 * sin))
+            });
+        }
+        BlurStyle::Zoom { center_x, center_y } => {
+            let (cx, cy) = (center_x * width as f32, center_y * height as f32);
+            // 最远处拖影长 2 * radius，向中心线性减弱
+            let (w, h) = (width as f32, height as f32);
+            let far = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)]
+                .iter()
+                .map(|(x, y)| (x - cx).hypot(y - cy))
+                .fold(1.0, f32::max);
+            let max_n = (radius * 2.0).ceil() as usize;
+            sweep(src, dst, y0, move |x, y| {
+                let (dx, dy) = (x - cx, y - cy);
+                let streak = 2.0 * radius * dx.hypot(dy) / far;
+                let n = (streak.ceil() as usize).clamp(1, max_n);
+                let step = if n > 1 { streak / (n - 1) as f32 } else { 0.0 };
+                let dist = dx.hypot(dy).max(f32::EPSILON);
+                (0..n).map(move |i| {
+                    let s = 1.0 + (i as f32 * step - streak / 2.0) / dist;
+                    (cx + dx * s, cy + dy * s)
+                })
+            });
+        }
+    }
+}
+
+/// 圆盘模糊。亮部以更大的权重参与平均，扩散成清晰的光斑，
+/// 权重为 1 + highlight * 亮度^8
+fn bokeh<S: Depth>(src: &Src<S>, dst: &mut [S], y0: usize, radius: f32, highlight: f32) {
+    let (width, edge) = (src.width, src.edge);
+    let r = radius.round() as isize;
+    let max = S::DEFAULT_MAX_VALUE.into_f64();
+    // 每行左右各延伸 r 个像素后的前缀和，通道为 [w*r, w*g, w*b, w]
+    let padded = width + 2 * r as usize;
+    let mut prefix = vec![0f64; (padded + 1) * 4 * (src.data.len() / (width * 3))];
+    prefix
+        .par_chunks_mut((padded + 1) * 4)
+        .zip(src.data.par_chunks(width * 3))
+        .for_each(|(p, row)| {
+            let mut acc = [0f64; 4];
+            for i in 0..padded {
+                let x = edge.index(i as isize - r, width) * 3;
+                let c = [row[x], row[x + 1], row[x + 2]].map(|v| v.into_f64());
+                let l = (0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2]) / max;
+                let w = 1.0 + highlight as f64 * l.clamp(0.0, 1.0).powi(8);
+                acc[0] += w * c[0];
+                acc[1] += w * c[1];
+                acc[2] += w * c[2];
+                acc[3] += w;
+                p[(i + 1) * 4..(i + 2) * 4].copy_from_slice(&acc);
+            }
+        });
+    // 圆盘每一行的半宽
+    let half: Vec<usize> = (-r..=r)
+        .map(|dy| {
+            let rr = radius as f64 + 0.5;
+            (rr * rr - (dy * dy) as f64).max(0.0).sqrt().floor() as usize
+        })
+        .collect();
+    dst.par_chunks_mut(width * 3)
+        .enumerate()
+        .for_each(|(i, row)| {
+            let y = (y0 + i) as isize;
+            for x in 0..width {
+                let mut sum = [0f64; 4];
+                for (k, dy) in (-r..=r).enumerate() {
+                    let p = &prefix[src.local(y + dy) * (padded + 1) * 4..];
+                    let hw = half[k].min(r as usize);
+                    let (a, b) = ((x + r as usize - hw) * 4, (x + r as usize + hw + 1) * 4);
+                    for c in 0..4 {
+                        sum[c] += p[b + c] - p[a + c];
+                    }
+                }
+                for c in 0..3 {
+                    row[x * 3 + c] = S::from_f64(sum[c] / sum[3]);
+                }
+            }
+        });
+}
+
+/// 每个像素取 samples 给出的各点(双线性插值)的平均，坐标按整幅计
+fn sweep<S, F, I>(src: &Src<S>, dst: &mut [S], y0: usize, samples: F)
+where
+    S: Depth,
+    F: Fn(f32, f32) -> I + Sync,
+    I: Iterator<Item = (f32, f32)>,
+{
+    let width = src.width;
+    let at = |x: isize, y: isize, c: usize| {
+        let i = src.local(y) * width + src.edge.index(x, width);
+        src.data[i * 3 + c].into_f64()
+    };
+    dst.par_chunks_mut(width * 3)
+        .enumerate()
+        .for_each(|(i, row)| {
+            let y = y0 + i;
+            for x in 0..width {
+                let mut sum = [0f64; 3];
+                let mut n = 0;
+                for (sx, sy) in samples(x as f32, y as f32) {
+                    let (x0, y0) = (sx.floor(), sy.floor());
+                    let (fx, fy) = ((sx - x0) as f64, (sy - y0) as f64);
+                    let (x0, y0) = (x0 as isize, y0 as isize);
+                    for (c, s) in sum.iter_mut().enumerate() {
+                        let top = at(x0, y0, c) * (1.0 - fx) + at(x0 + 1, y0, c) * fx;
+                        let bottom = at(x0, y0 + 1, c) * (1.0 - fx) + at(x0 + 1, y0 + 1, c) * fx;
+                        *s += top * (1.0 - fy) + bottom * fy;
+                    }
+                    n += 1;
+                }
+                for c in 0..3 {
+                    row[x * 3 + c] = S::from_f64(sum[c] / n as f64);
+                }
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLES: [BlurStyle; 3] = [
+        BlurStyle::Bokeh { highlight: 4.0 },
+        BlurStyle::Motion { angle: 30.0 },
+        BlurStyle::Zoom {
+            center_x: 0.3,
+            center_y: 0.6,
+        },
+    ];
+
+    #[test]
+    fn flat() {
+        for style in STYLES.iter() {
+            let mut a = vec![90u8; 40 * 30 * 3];
+            apply(style, &mut a, 40, 30, 6.0, Edge::Mirror);
+            assert!(a.iter().all(|&v| v == 90), "{style:?}");
+        }
+    }
+
+    #[test]
+    fn motion() {
+        // 单个亮点沿水平方向拉成线段，竖直方向不扩散
+        let (w, h) = (41, 21);
+        let mut a = vec![0u16; w * h * 3];
+        a[(10 * w + 20) * 3] = 60000;
+        apply(
+            &BlurStyle::Motion { angle: 0.0 },
+            &mut a,
+            w,
+            h,
+            5.0,
+            Edge::Clamp,
+        );
+        let at = |x: usize, y: usize| a[(y * w + x) * 3];
+        assert!((15..=25).all(|x| at(x, 10) > 0));
+        assert_eq!(at(14, 10), 0);
+        assert!((0..w).all(|x| at(x, 9) == 0 && at(x, 11) == 0));
+    }
+
+    #[test]
+    fn disk() {
+        // 亮点扩散为边缘清晰、内部平坦的圆盘
+        let (w, h) = (41, 41);
+        let mut a = vec![0f32; w * h * 3];
+        for c in 0..3 {
+            a[(20 * w + 20) * 3 + c] = 1.0;
+        }
+        let style = BlurStyle::Bokeh { highlight: 0.0 };
+        apply(&style, &mut a, w, h, 8.0, Edge::Clamp);
+        let at = |x: usize, y: usize| a[(y * w + x) * 3];
+        assert!((at(20, 20) - at(25, 20)).abs() < 1e-6);
+        assert!((at(20, 20) - at(20, 14)).abs() < 1e-6);
+        assert!(at(20, 20) > 0.0);
+        assert_eq!(at(29, 20), 0.0);
+        assert_eq!(at(27, 27), 0.0);
+        // 增强后亮点占比更高
+        let mut b = vec![0.2f32; w * h * 3];
+        for c in 0..3 {
+            b[(20 * w + 20) * 3 + c] = 1.0;
+        }
+        let mut plain = b.clone();
+        apply(&style, &mut plain, w, h, 8.0, Edge::Clamp);
+        let boosted = BlurStyle::Bokeh { highlight: 20.0 };
+        apply(&boosted, &mut b, w, h, 8.0, Edge::Clamp);
+        assert!(b[(20 * w + 20) * 3] > plain[(20 * w + 20) * 3] + 0.05);
+    }
+
+    #[test]
+    fn zoom() {
+        // 中心清晰，远处沿径向拖影
+        let (w, h) = (41, 41);
+        let src: Vec<u8> = (0..w * h * 3).map(|i| (i * 37 % 251) as u8).collect();
+        let mut a = src.clone();
+        let style = BlurStyle::Zoom {
+            center_x: 20.0 / 41.0,
+            center_y: 20.0 / 41.0,
+        };
+        apply(&style, &mut a, w, h, 6.0, Edge::Clamp);
+        assert_eq!(a[(20 * w + 20) * 3], src[(20 * w + 20) * 3]);
+        assert_ne!(a[..w * 3], src[..w * 3]);
+    }
+
+    #[test]
+    fn banded() {
+        // 条高小于 halo、边缘取值不同时，分条与整幅结果一致
+        let (w, h) = (37, 61);
+        let src: Vec<u8> = (0..w * h * 3).map(|i| (i * 37 % 251) as u8).collect();
+        let styles = STYLES.iter().chain(&[
+            BlurStyle::Motion { angle: 90.0 },
+            BlurStyle::Motion { angle: -135.0 },
+        ]);
+        for style in styles {
+            for edge in [Edge::Clamp, Edge::Mirror] {
+                let mut full = src.clone();
+                apply(style, &mut full, w, h, 5.0, edge);
+                for budget in [1, 4 << 10, 16 << 10, 64 << 10] {
+                    let mut tiled = src.clone();
+                    apply_banded(style, &mut tiled, w, h, 5.0, edge, Some(budget));
+                    assert_eq!(full, tiled, "{style:?} {edge:?} {budget}");
+                }
+            }
+        }
+    }
+}
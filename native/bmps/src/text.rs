@@ -0,0 +1,436 @@
+use std::{collections::HashMap, path::Path};
+
+use image::{GenericImage, Pixel, Rgba, RgbaImage};
+use rusttype::{point, Font, Scale};
+
+use crate::config::{Caption, Span, TextEffect};
+use crate::effects;
+
+pub mod contrast;
+pub mod rich;
+pub mod vertical;
+
+pub fn load_font<P: AsRef<Path>>(path: P) -> anyhow::Result<Font<'static>> {
+    let data = std::fs::read(path)?;
+    Font::try_from_vec(data).ok_or_else(|| anyhow::Error::msg("font invalid"))
+}
+
+/// 按路径缓存已加载的字体
+#[derive(Default)]
+pub struct FontBook {
+    fonts: HashMap<String, Font<'static>>,
+}
+
+impl FontBook {
+    pub fn new() -> FontBook {
+        Default::default()
+    }
+
+    /// 为每个 span 选取字体，未指定时使用 default
+    pub fn resolve(
+        &mut self,
+        spans: &[Span],
+        default: Option<&String>,
+    ) -> anyhow::Result<Vec<&Font<'static>>> {
+        let mut paths = vec![];
+        for s in spans {
+            let path = s
+                .font
+                .as_ref()
+                .or(default)
+                .ok_or_else(|| anyhow::Error::msg("font missing"))?;
+            if !self.fonts.contains_key(path) {
+                self.fonts.insert(path.clone(), load_font(path)?);
+            }
+            paths.push(path);
+        }
+        Ok(paths.into_iter().map(|p| &self.fonts[p]).collect())
+    }
+}
+
+/// 画布上的矩形区域，如照片四周的留白
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// 绘制标题：先在透明图层上排版，按需根据背景自动取色，
+/// 再依次混合文字特效与文字本身到画布
+pub fn draw<I: GenericImage<Pixel = Rgba<u8>>>(
+    canvas: &mut I,
+    fonts: &[&Font],
+    caption: &Caption,
+    region: Region,
+) {
+    // 留出字号大小的边距，容纳超出区域的文字和阴影
+    let size = caption.spans.iter().map(|s| s.size).fold(0.0, f32::max);
+    let margin = size.ceil() as u32;
+    let mut layer = RgbaImage::new(region.width + margin * 2, region.height + margin * 2);
+    let inner = Region {
+        x: margin as i32,
+        y: margin as i32,
+        ..region
+    };
+    if caption.vertical {
+        vertical::draw(&mut layer, fonts, caption, inner);
+    } else {
+        rich::draw(&mut layer, fonts, caption, inner);
+    }
+    let x = region.x - margin as i32;
+    let y = region.y - margin as i32;
+    let Some(bbox) = bounding_box(&layer) else {
+        return;
+    };
+    let mut effects = caption.effects.clone();
+    if let Some(auto) = caption.auto_color.as_ref() {
+        let area = Region {
+            x: x + bbox.x,
+            y: y + bbox.y,
+            ..bbox
+        };
+        if let Some(choice) = contrast::measure(canvas, area).map(contrast::choose) {
+            log::info!("caption color {:?}", choice);
+            layer
+                .pixels_mut()
+                .for_each(|p| p.0[..3].copy_from_slice(&choice.color));
+            if auto.shadow && choice.contrast < auto.min_contrast {
+                // 对比度不足时在文字下衬一层反色的淡阴影
+                let mut color = [0, 0, 0, 128];
+                color[..3].copy_from_slice(if choice.color == contrast::LIGHT {
+                    &contrast::DARK
+                } else {
+                    &contrast::LIGHT
+                });
+                let offset = (size / 24.0).ceil() as i32;
+                effects.push(TextEffect::Shadow {
+                    offset_x: offset,
+                    offset_y: offset,
+                    blur_radius: (size / 12.0).ceil() as u32,
+                    color,
+                });
+            }
+        }
+    }
+    for e in effects.iter() {
+        let (bg, dx, dy) = match *e {
+            TextEffect::Shadow {
+                offset_x,
+                offset_y,
+                blur_radius,
+                color,
+            } => effects::shadow::Builder::new()
+                .offset(offset_x, offset_y)
+                .blur_radius(blur_radius)
+                .color(color)
+                .build()
+                .gen_bg(&layer),
+            TextEffect::Outline { width, color } => effects::outline::Builder::new()
+                .width(width)
+                .color(color)
+                .build()
+                .gen_bg(&layer),
+            TextEffect::Glow {
+                radius,
+                spread,
+                color,
+            } => effects::glow::Builder::new()
+                .radius(radius)
+                .spread(spread)
+                .color(color)
+                .build()
+                .gen_bg(&layer),
+        };
+        crate::overlay(canvas, &bg, x - dx as i32, y - dy as i32);
+    }
+    crate::overlay(canvas, &layer, x, y);
+}
+
+/// 图层中不透明部分的外接矩形
+pub fn bounding_box(layer: &RgbaImage) -> Option<Region> {
+    let (mut x0, mut y0, mut x1, mut y1) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, p) in layer.enumerate_pixels() {
+        if p.0[3] > 0 {
+            x0 = x0.min(x);
+            y0 = y0.min(y);
+            x1 = x1.max(x);
+            y1 = y1.max(y);
+        }
+    }
+    if x0 > x1 {
+        return None;
+    }
+    Some(Region {
+        x: x0 as i32,
+        y: y0 as i32,
+        width: x1 - x0 + 1,
+        height: y1 - y0 + 1,
+    })
+}
+
+/// 字形覆盖率，取值 [0, 1]
+#[derive(Debug, Clone, Default)]
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    data: Vec<f32>,
+}
+
+/// 合成粗体的描边宽度（像素），字重 500 及以下不加粗
+pub fn embolden(span: &Span) -> f32 {
+    if span.weight <= 500 {
+        return 0.0;
+    }
+    span.size * (span.weight - 400) as f32 / 6000.0
+}
+
+/// 合成细体时笔画收缩的总宽度（像素），两侧各收缩一半，与 embolden 对称，字重 400 及以上不收缩
+pub fn lighten(span: &Span) -> f32 {
+    if span.weight >= 400 {
+        return 0.0;
+    }
+    span.size * (400 - span.weight) as f32 / 6000.0
+}
+
+impl Mask {
+    pub fn new(width: u32, height: u32) -> Mask {
+        Mask {
+            width,
+            height,
+            data: vec![0.0; (width * height) as usize],
+        }
+    }
+
+    /// 以 (x, y) 为笔位(基线)光栅化单个字形，返回蒙版及其左上角坐标
+    pub fn glyph(font: &Font, c: char, span: &Span, x: f32, y: f32) -> Option<(Mask, i32, i32)> {
+        let g = font
+            .glyph(c)
+            .scaled(Scale::uniform(span.size))
+            .positioned(point(x, y));
+        let bb = g.pixel_bounding_box()?;
+        let bold = embolden(span);
+        let extra = bold.ceil() as i32;
+        let mut mask = Mask::new((bb.width() + extra) as u32, bb.height() as u32);
+        g.draw(|gx, gy, v| {
+            for k in 0..=extra {
+                let w = (bold - k as f32 + 1.0).min(1.0);
+                mask.add(gx as i32 + k, gy as i32, v * w);
+            }
+        });
+        mask.erode(lighten(span));
+        Some((mask, bb.min.x, bb.min.y))
+    }
+
+    /// 按 span 样式横排一段文字，基线位于 ascent 处
+    pub fn from_span(font: &Font, text: &str, span: &Span) -> Mask {
+        let v = font.v_metrics(Scale::uniform(span.size));
+        let width = span_width(font, text, span).ceil() as u32;
+        let height = (v.ascent - v.descent).ceil() as u32;
+        let mut mask = Mask::new(width, height);
+        let mut pen = 0.0;
+        let mut last = None;
+        for c in text.chars() {
+            pen += kerning(font, span, last, c);
+            last = Some(c);
+            if let Some((m, x, y)) = Mask::glyph(font, c, span, pen, v.ascent) {
+                mask.merge(&m, x, y);
+            }
+            pen += advance(font, span, c);
+        }
+        mask
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        self.data[(y * self.width + x) as usize]
+    }
+
+    fn add(&mut self, x: i32, y: i32, v: f32) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let p = &mut self.data[(y as u32 * self.width + x as u32) as usize];
+        *p = (*p + v).min(1.0);
+    }
+
+    /// 以半径 amount/2 的圆盘腐蚀，即加粗的逆过程：笔画四周同时收缩，
+    /// 细化后字形重心不偏移。取邻域内最小覆盖率，圆盘边缘按距离计入部分权重
+    fn erode(&mut self, amount: f32) {
+        if amount <= 0.0 {
+            return;
+        }
+        let r = amount / 2.0;
+        let extra = r.ceil() as i32;
+        let src = self.data.clone();
+        let at = |x: i32, y: i32| {
+            if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+                return 0.0;
+            }
+            src[(y as u32 * self.width + x as u32) as usize]
+        };
+        for y in 0..self.height as i32 {
+            for x in 0..self.width as i32 {
+                let mut v = at(x, y);
+                for dy in -extra..=extra {
+                    for dx in -extra..=extra {
+                        let w = (r - ((dx * dx + dy * dy) as f32).sqrt() + 1.0).clamp(0.0, 1.0);
+                        if w > 0.0 && (dx, dy) != (0, 0) {
+                            v = v.min(1.0 - w * (1.0 - at(x + dx, y + dy)));
+                        }
+                    }
+                }
+                self.data[(y as u32 * self.width + x as u32) as usize] = v;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &Mask, x: i32, y: i32) {
+        for oy in 0..other.height {
+            for ox in 0..other.width {
+                self.add(x + ox as i32, y + oy as i32, other.get(ox, oy));
+            }
+        }
+    }
+
+    /// 顺时针旋转 90 度
+    pub fn rotate_cw(&self) -> Mask {
+        let mut res = Mask::new(self.height, self.width);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                res.data[(x * res.width + (self.height - 1 - y)) as usize] = self.get(x, y);
+            }
+        }
+        res
+    }
+
+    /// 以 (x, y) 为左上角将蒙版按 color 混合到画布，超出画布的部分忽略
+    pub fn draw_to<I: GenericImage<Pixel = Rgba<u8>>>(
+        &self,
+        canvas: &mut I,
+        x: i32,
+        y: i32,
+        color: [u8; 4],
+    ) {
+        let (w, h) = canvas.dimensions();
+        for my in 0..self.height {
+            let cy = y + my as i32;
+            if cy < 0 || cy as u32 >= h {
+                continue;
+            }
+            for mx in 0..self.width {
+                let cx = x + mx as i32;
+                let v = self.get(mx, my);
+                if v <= 0.0 || cx < 0 || cx as u32 >= w {
+                    continue;
+                }
+                let mut c = color;
+                c[3] = (color[3] as f32 * v).round() as u8;
+                let mut p = canvas.get_pixel(cx as u32, cy as u32);
+                p.blend(&Rgba(c));
+                canvas.put_pixel(cx as u32, cy as u32, p);
+            }
+        }
+    }
+}
+
+/// 单字步进，含字间距与合成粗体的加宽
+pub fn advance(font: &Font, span: &Span, c: char) -> f32 {
+    let scale = Scale::uniform(span.size);
+    font.glyph(c).scaled(scale).h_metrics().advance_width + span.letter_spacing + embolden(span)
+}
+
+/// 同一 span 内相邻两字的字距调整
+pub fn kerning(font: &Font, span: &Span, prev: Option<char>, c: char) -> f32 {
+    match prev {
+        Some(p) => font.pair_kerning(Scale::uniform(span.size), p, c),
+        None => 0.0,
+    }
+}
+
+/// 按 span 样式横排一段文字的宽度（像素）
+pub fn span_width(font: &Font, text: &str, span: &Span) -> f32 {
+    let mut width = 0.0;
+    let mut last = None;
+    for c in text.chars() {
+        width += kerning(font, span, last, c) + advance(font, span, c);
+        last = Some(c);
+    }
+    width
+}
+
+/// 中日韩文字（含全角标点），竖排时直立书写
+pub fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x1100..=0x11FF // Hangul Jamo
+        | 0x2E80..=0x2FDF // CJK Radicals
+        | 0x3000..=0x303F // CJK Symbols and Punctuation
+        | 0x3040..=0x30FF // Hiragana, Katakana
+        | 0x3100..=0x31FF // Bopomofo, Kanbun ...
+        | 0x3200..=0x33FF // Enclosed CJK, Compatibility
+        | 0x3400..=0x4DBF // Extension A
+        | 0x4E00..=0x9FFF // Unified Ideographs
+        | 0xAC00..=0xD7AF // Hangul Syllables
+        | 0xF900..=0xFAFF // Compatibility Ideographs
+        | 0xFE30..=0xFE4F // CJK Compatibility Forms
+        | 0xFF00..=0xFFEF // Halfwidth and Fullwidth Forms
+        | 0x20000..=0x3134F)
+}
+
+/// 测试用字体：DejaVu Sans 中可打印 ASCII 的子集
+#[cfg(test)]
+pub(crate) fn test_font() -> Font<'static> {
+    Font::try_from_bytes(include_bytes!("../tests/fixtures/DejaVuSans.ttf")).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn light_weight() {
+        let font = test_font();
+        let coverage = |weight: u16| {
+            let span = Span {
+                weight,
+                size: 48.0,
+                ..Default::default()
+            };
+            let mask = Mask::from_span(&font, "Light Hamburgefonstiv", &span);
+            mask.data.iter().sum::<f32>()
+        };
+        let (w100, w300, w400, w700) = (coverage(100), coverage(300), coverage(400), coverage(700));
+        assert!(w300 < w400 * 0.95, "{w300} {w400}");
+        assert!(w100 < w300, "{w100} {w300}");
+        assert!(w400 < w700);
+        // 字形仍然可见
+        assert!(w100 > w400 * 0.3, "{w100} {w400}");
+        // 四周均匀收缩，中心对称字形覆盖率的重心不偏移
+        let centroid = |c: char, weight: u16| {
+            let span = Span {
+                weight,
+                size: 48.0,
+                ..Default::default()
+            };
+            let (m, left, top) = Mask::glyph(&font, c, &span, 0.0, 48.0).unwrap();
+            let (mut sx, mut sy, mut sum) = (0.0, 0.0, 0.0);
+            for y in 0..m.height {
+                for x in 0..m.width {
+                    let v = m.get(x, y);
+                    sx += x as f32 * v;
+                    sy += y as f32 * v;
+                    sum += v;
+                }
+            }
+            (left as f32 + sx / sum, top as f32 + sy / sum)
+        };
+        for c in "HIOo".chars() {
+            let (x400, y400) = centroid(c, 400);
+            for weight in [100, 300] {
+                let (x, y) = centroid(c, weight);
+                assert!((x - x400).abs() < 0.25, "{c} {weight}: {x} {x400}");
+                assert!((y - y400).abs() < 0.25, "{c} {weight}: {y} {y400}");
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! 根据文字背后区域的亮度选择浅色或深色文字。
+//! 相对亮度与对比度的定义见 [WCAG 2.1](https://www.w3.org/TR/WCAG21/#dfn-contrast-ratio)
+use image::{GenericImageView, Rgba};
+
+use super::Region;
+
+pub const LIGHT: [u8; 3] = [255, 255, 255];
+pub const DARK: [u8; 3] = [20, 20, 20];
+
+fn linear(v: u8) -> f64 {
+    let v = v as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// 相对亮度，取值 [0, 1]
+pub fn luminance(rgb: [u8; 3]) -> f64 {
+    0.2126 * linear(rgb[0]) + 0.7152 * linear(rgb[1]) + 0.0722 * linear(rgb[2])
+}
+
+/// 对比度，取值 [1, 21]
+pub fn contrast_ratio(a: f64, b: f64) -> f64 {
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// 背景亮度分布，取 10% 与 90% 分位数，忽略零星的极端像素
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backdrop {
+    pub dark: f64,
+    pub light: f64,
+}
+
+pub fn measure<I: GenericImageView<Pixel = Rgba<u8>>>(img: &I, region: Region) -> Option<Backdrop> {
+    let (w, h) = img.dimensions();
+    let x0 = region.x.max(0) as u32;
+    let y0 = region.y.max(0) as u32;
+    let x1 = ((region.x + region.width as i32).max(0) as u32).min(w);
+    let y1 = ((region.y + region.height as i32).max(0) as u32).min(h);
+    // 大区域隔点采样
+    let step = ((x1.saturating_sub(x0) * y1.saturating_sub(y0)) as f64 / 65536.0)
+        .sqrt()
+        .max(1.0) as usize;
+    let mut values = vec![];
+    for y in (y0..y1).step_by(step) {
+        for x in (x0..x1).step_by(step) {
+            let p = img.get_pixel(x, y).0;
+            values.push(luminance([p[0], p[1], p[2]]));
+        }
+    }
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let at = |q: f64| values[((values.len() - 1) as f64 * q).round() as usize];
+    Some(Backdrop {
+        dark: at(0.1),
+        light: at(0.9),
+    })
+}
+
+/// 选中的文字颜色及其在背景最不利处的对比度
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Choice {
+    pub color: [u8; 3],
+    pub contrast: f64,
+}
+
+pub fn choose(b: Backdrop) -> Choice {
+    // 浅色文字最怕背景中的亮处，深色文字最怕暗处
+    let light = contrast_ratio(luminance(LIGHT), b.light);
+    let dark = contrast_ratio(luminance(DARK), b.dark);
+    if light >= dark {
+        Choice {
+            color: LIGHT,
+            contrast: light,
+        }
+    } else {
+        Choice {
+            color: DARK,
+            contrast: dark,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio() {
+        let white = luminance([255; 3]);
+        let black = luminance([0; 3]);
+        assert!((contrast_ratio(white, black) - 21.0).abs() < 1e-9);
+        assert!((contrast_ratio(black, white) - 21.0).abs() < 1e-9);
+        // WCAG 示例：#777 对白色约 4.48
+        let gray = luminance([0x77; 3]);
+        assert!((contrast_ratio(gray, white) - 4.48).abs() < 0.01);
+    }
+
+    #[test]
+    fn pick() {
+        let sky = image::RgbaImage::from_pixel(20, 10, Rgba([220, 230, 250, 255]));
+        let region = Region {
+            x: 0,
+            y: 0,
+            width: 20,
+            height: 10,
+        };
+        let b = measure(&sky, region).unwrap();
+        assert_eq!(choose(b).color, DARK);
+        let night = image::RgbaImage::from_pixel(20, 10, Rgba([10, 20, 40, 255]));
+        let c = choose(measure(&night, region).unwrap());
+        assert_eq!(c.color, LIGHT);
+        assert!(c.contrast > 4.5);
+    }
+}
@@ -0,0 +1,240 @@
+//! 多样式横排：各 span 共用基线，同一 span 内按字体做字距调整，
+//! 超出宽度时在空白或中日韩文字处折行。
+use image::{GenericImage, Rgba};
+use rusttype::{Font, Scale};
+
+use super::{advance, is_cjk, kerning, Mask, Region};
+use crate::config::{Align, Caption, Side, Span};
+
+/// 一个字，x 为相对行首的笔位
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub span: usize,
+    pub c: char,
+    pub x: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Line {
+    pub items: Vec<Item>,
+    // 不含行尾空白的宽度
+    pub width: f32,
+    pub ascent: f32,
+    pub descent: f32,
+    // 行顶相对排版块的位置
+    pub y: f32,
+    pub height: f32,
+    // 空行时用于计算行高
+    span: usize,
+}
+
+impl Line {
+    /// 基线相对排版块的位置，字形在行内竖直居中
+    pub fn baseline(&self) -> f32 {
+        self.y + (self.height - (self.ascent - self.descent)) / 2.0 + self.ascent
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub lines: Vec<Line>,
+    pub width: f32,
+    pub height: f32,
+}
+
+// 折行的最小单位：一个词(含其后空白)或一个中日韩字
+enum Cluster {
+    Word(Vec<(usize, char)>),
+    Newline(usize),
+}
+
+// 避头标点，跟随前一个字
+fn no_break_before(c: char) -> bool {
+    "，。、；：！？）」』》〉】〕…".contains(c)
+}
+
+fn clusters(spans: &[Span]) -> Vec<Cluster> {
+    let mut res = vec![];
+    let mut word = vec![];
+    for (i, s) in spans.iter().enumerate() {
+        for c in s.text.chars() {
+            if c == '\n' {
+                if !word.is_empty() {
+                    res.push(Cluster::Word(std::mem::take(&mut word)));
+                }
+                res.push(Cluster::Newline(i));
+            } else if no_break_before(c) {
+                match res.last_mut() {
+                    Some(Cluster::Word(w)) if word.is_empty() => w.push((i, c)),
+                    _ => {
+                        word.push((i, c));
+                        res.push(Cluster::Word(std::mem::take(&mut word)));
+                    }
+                }
+            } else if is_cjk(c) {
+                if !word.is_empty() {
+                    res.push(Cluster::Word(std::mem::take(&mut word)));
+                }
+                res.push(Cluster::Word(vec![(i, c)]));
+            } else {
+                word.push((i, c));
+                if c.is_whitespace() {
+                    res.push(Cluster::Word(std::mem::take(&mut word)));
+                }
+            }
+        }
+    }
+    if !word.is_empty() {
+        res.push(Cluster::Word(word));
+    }
+    res
+}
+
+/// 按 max_width 折行，line_spacing 为行距相对该行最大字号的倍数
+pub fn layout(fonts: &[&Font], spans: &[Span], max_width: f32, line_spacing: f32) -> Layout {
+    let kern = |prev: Option<(usize, char)>, (i, c): (usize, char)| match prev {
+        Some((j, p)) if j == i => kerning(fonts[i], &spans[i], Some(p), c),
+        _ => 0.0,
+    };
+    let mut lines = vec![Line::default()];
+    let mut pen = 0.0;
+    let mut prev = None;
+    for cluster in clusters(spans) {
+        let word = match cluster {
+            Cluster::Newline(span) => {
+                lines.last_mut().unwrap().span = span;
+                lines.push(Line {
+                    span,
+                    ..Default::default()
+                });
+                pen = 0.0;
+                prev = None;
+                continue;
+            }
+            Cluster::Word(w) => w,
+        };
+        let mut visible = 0.0;
+        let mut w = 0.0;
+        let mut p = prev;
+        for &(i, c) in word.iter() {
+            w += kern(p, (i, c)) + advance(fonts[i], &spans[i], c);
+            if !c.is_whitespace() {
+                visible = w;
+            }
+            p = Some((i, c));
+        }
+        let line = lines.last_mut().unwrap();
+        if !line.items.is_empty() && pen + visible > max_width {
+            lines.push(Line {
+                span: word[0].0,
+                ..Default::default()
+            });
+            pen = 0.0;
+            prev = None;
+        }
+        let line = lines.last_mut().unwrap();
+        if line.items.is_empty() && word.iter().all(|(_, c)| c.is_whitespace()) {
+            continue;
+        }
+        for (i, c) in word {
+            pen += kern(prev, (i, c));
+            line.items.push(Item { span: i, c, x: pen });
+            pen += advance(fonts[i], &spans[i], c);
+            if !c.is_whitespace() {
+                line.width = pen;
+            }
+            prev = Some((i, c));
+        }
+    }
+    let mut res = Layout::default();
+    for mut line in lines {
+        let mut used: Vec<usize> = line.items.iter().map(|it| it.span).collect();
+        if used.is_empty() {
+            used.push(line.span);
+        }
+        let mut size: f32 = 0.0;
+        for i in used {
+            let Some(span) = spans.get(i) else {
+                continue;
+            };
+            let v = fonts[i].v_metrics(Scale::uniform(span.size));
+            line.ascent = line.ascent.max(v.ascent);
+            line.descent = line.descent.min(v.descent);
+            size = size.max(span.size);
+        }
+        line.height = (size * line_spacing).max(line.ascent - line.descent);
+        line.y = res.height;
+        res.height += line.height;
+        res.width = res.width.max(line.width);
+        res.lines.push(line);
+    }
+    res
+}
+
+/// 在 region 中横排绘制标题；位于照片上下方时在区域内竖直居中
+pub fn draw<I: GenericImage<Pixel = Rgba<u8>>>(
+    canvas: &mut I,
+    fonts: &[&Font],
+    caption: &Caption,
+    region: Region,
+) {
+    let spans = caption.spans.as_slice();
+    let l = layout(fonts, spans, region.width as f32, caption.line_spacing);
+    let oy = match caption.side {
+        Side::Top | Side::Bottom => region.y as f32 + (region.height as f32 - l.height) / 2.0,
+        Side::Left | Side::Right => region.y as f32,
+    };
+    for line in l.lines.iter() {
+        let ox = region.x as f32
+            + match caption.align {
+                Align::Start => 0.0,
+                Align::Center => (region.width as f32 - line.width) / 2.0,
+                Align::End => region.width as f32 - line.width,
+            };
+        let baseline = oy + line.baseline();
+        for it in line.items.iter() {
+            let span = &spans[it.span];
+            if let Some((m, x, y)) = Mask::glyph(fonts[it.span], it.c, span, ox + it.x, baseline) {
+                m.draw_to(canvas, x, y, span.color);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap() {
+        let font = crate::text::test_font();
+        let spans = vec![
+            Span {
+                text: "Leica M6 ".to_owned(),
+                weight: 700,
+                size: 20.0,
+                ..Default::default()
+            },
+            Span {
+                text: "f/2.8 1/125s ISO400".to_owned(),
+                size: 10.0,
+                color: [128, 128, 128, 255],
+                ..Default::default()
+            },
+        ];
+        let fonts = vec![&font, &font];
+        let one = layout(&fonts, &spans, 10000.0, 1.5);
+        assert_eq!(one.lines.len(), 1);
+        // 两个 span 共用基线，行高取较大字号
+        assert_eq!(one.lines[0].height, 30.0);
+
+        let l = layout(&fonts, &spans, one.width - 1.0, 1.5);
+        assert_eq!(l.lines.len(), 2);
+        assert!(l.width < one.width);
+        // 折行处的空白不计入行宽，也不出现在行首
+        let last = &l.lines[1];
+        assert_eq!(last.items[0].c, 'I');
+        assert_eq!(last.items[0].x, 0.0);
+        assert_eq!(last.height, 15.0);
+    }
+}
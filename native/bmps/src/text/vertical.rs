@@ -0,0 +1,245 @@
+//! 竖排：字自上而下，列自右向左。
+//! 中日韩文字直立，拉丁文字、数字按词顺时针旋转 90 度，
+//! 句读点移至字格右上角，括号、破折号等随行旋转。
+use image::{GenericImage, Rgba};
+use rusttype::Font;
+
+use super::{is_cjk, span_width, Mask, Region};
+use crate::config::{Align, Caption, Span};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Glyph {
+    Upright(char),
+    // 、。，． 等，直立但位于字格右上角
+    Shifted(char),
+    // 拉丁文字(一个词)或需旋转的全角标点
+    Rotated(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Glyph(usize, Glyph),
+    Break,
+}
+
+/// 排版结果中的一个字（词），(x, y) 为字格左上角，相对于排版块
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placed {
+    pub span: usize,
+    pub glyph: Glyph,
+    // 所在列，自右向左从 0 计
+    pub column: usize,
+    pub x: f32,
+    pub y: f32,
+    // 竖直方向占用的长度
+    pub length: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    pub glyphs: Vec<Placed>,
+    // 各列的长度
+    pub columns: Vec<f32>,
+    pub width: f32,
+    pub height: f32,
+}
+
+fn classify(c: char) -> Glyph {
+    match c {
+        '、' | '。' | '，' | '．' | '｡' | '､' => Glyph::Shifted(c),
+        '（' | '）' | '「' | '」' | '『' | '』' | '《' | '》' | '〈' | '〉' | '【' | '】'
+        | '〔' | '〕' | '［' | '］' | '｛' | '｝' | '〖' | '〗' | '～' | '〜' | 'ー' | '－'
+        | '＿' | '：' | '；' | '＝' => Glyph::Rotated(c.to_string()),
+        _ => Glyph::Upright(c),
+    }
+}
+
+fn tokenize(spans: &[Span]) -> Vec<Token> {
+    let mut res = vec![];
+    let mut run = String::new();
+    let flush = |i: usize, run: &mut String, res: &mut Vec<Token>| {
+        if !run.is_empty() {
+            res.push(Token::Glyph(i, Glyph::Rotated(std::mem::take(run))));
+        }
+    };
+    for (i, s) in spans.iter().enumerate() {
+        for c in s.text.chars() {
+            if c == '\n' {
+                flush(i, &mut run, &mut res);
+                res.push(Token::Break);
+            } else if is_cjk(c) {
+                flush(i, &mut run, &mut res);
+                res.push(Token::Glyph(i, classify(c)));
+            } else {
+                run.push(c);
+                // 在空格处断词，便于换列
+                if c.is_whitespace() {
+                    flush(i, &mut run, &mut res);
+                }
+            }
+        }
+        flush(i, &mut run, &mut res);
+    }
+    res
+}
+
+/// 按列高 max_height 自动换列，line_spacing 为列距相对最大字号的倍数
+pub fn layout(fonts: &[&Font], spans: &[Span], line_spacing: f32, max_height: f32) -> Layout {
+    let mut columns: Vec<Vec<Placed>> = vec![vec![]];
+    let mut y = 0.0;
+    for t in tokenize(spans) {
+        let (span, glyph) = match t {
+            Token::Break => {
+                columns.push(vec![]);
+                y = 0.0;
+                continue;
+            }
+            Token::Glyph(i, g) => (i, g),
+        };
+        let s = &spans[span];
+        let length = match &glyph {
+            Glyph::Rotated(text) => span_width(fonts[span], text.as_str(), s),
+            _ => s.size + s.letter_spacing,
+        };
+        let col = columns.last_mut().unwrap();
+        if y + length > max_height && !col.is_empty() {
+            columns.push(vec![]);
+            y = 0.0;
+        }
+        columns.last_mut().unwrap().push(Placed {
+            span,
+            glyph,
+            column: 0,
+            x: 0.0,
+            y,
+            length,
+        });
+        y += length;
+    }
+    let size = spans.iter().map(|s| s.size).fold(0.0, f32::max);
+    let pitch = size * line_spacing;
+    let width = (columns.len() - 1) as f32 * pitch + size;
+    let mut res = Layout {
+        width,
+        ..Default::default()
+    };
+    for (i, col) in columns.into_iter().enumerate() {
+        let x = width - size - i as f32 * pitch;
+        let mut length: f32 = 0.0;
+        for mut p in col {
+            // 较小的字在列内水平居中
+            p.x = x + (size - spans[p.span].size) / 2.0;
+            p.column = i;
+            length = length.max(p.y + p.length);
+            res.glyphs.push(p);
+        }
+        res.columns.push(length);
+        res.height = res.height.max(length);
+    }
+    res
+}
+
+/// 在 region 中竖排绘制标题，排版块水平居中，各列按 align 竖直对齐
+pub fn draw<I: GenericImage<Pixel = Rgba<u8>>>(
+    canvas: &mut I,
+    fonts: &[&Font],
+    caption: &Caption,
+    region: Region,
+) {
+    let spans = caption.spans.as_slice();
+    let l = layout(fonts, spans, caption.line_spacing, region.height as f32);
+    let ox = region.x as f32 + (region.width as f32 - l.width) / 2.0;
+    for p in l.glyphs.iter() {
+        let free = region.height as f32 - l.columns[p.column];
+        let oy = region.y as f32
+            + match caption.align {
+                Align::Start => 0.0,
+                Align::Center => free / 2.0,
+                Align::End => free,
+            };
+        let span = &spans[p.span];
+        let size = span.size;
+        let font = fonts[p.span];
+        let (mask, dx, dy) = match &p.glyph {
+            Glyph::Upright(c) => {
+                let m = Mask::from_span(font, c.to_string().as_str(), span);
+                let dx = (size - m.width as f32) / 2.0;
+                let dy = (size - m.height as f32) / 2.0;
+                (m, dx, dy)
+            }
+            Glyph::Shifted(c) => {
+                let m = Mask::from_span(font, c.to_string().as_str(), span);
+                let dx = (size - m.width as f32) / 2.0 + size / 2.0;
+                let dy = (size - m.height as f32) / 2.0 - size / 2.0;
+                (m, dx, dy)
+            }
+            Glyph::Rotated(s) => {
+                let m = Mask::from_span(font, s.as_str(), span).rotate_cw();
+                let dx = (size - m.width as f32) / 2.0;
+                (m, dx, 0.0)
+            }
+        };
+        mask.draw_to(
+            canvas,
+            (ox + p.x + dx).round() as i32,
+            (oy + p.y + dy).round() as i32,
+            span.color,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens() {
+        let spans = vec![
+            Span {
+                text: "徕卡 M6，".to_owned(),
+                ..Default::default()
+            },
+            Span {
+                text: "胶片\n「人像」".to_owned(),
+                ..Default::default()
+            },
+        ];
+        let g = |i, g| Token::Glyph(i, g);
+        assert_eq!(
+            tokenize(&spans),
+            vec![
+                g(0, Glyph::Upright('徕')),
+                g(0, Glyph::Upright('卡')),
+                g(0, Glyph::Rotated(" ".to_owned())),
+                g(0, Glyph::Rotated("M6".to_owned())),
+                g(0, Glyph::Shifted('，')),
+                g(1, Glyph::Upright('胶')),
+                g(1, Glyph::Upright('片')),
+                Token::Break,
+                g(1, Glyph::Rotated("「".to_owned())),
+                g(1, Glyph::Upright('人')),
+                g(1, Glyph::Upright('像')),
+                g(1, Glyph::Rotated("」".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn columns() {
+        let font = crate::text::test_font();
+        let spans = vec![Span {
+            text: "一二三四五".to_owned(),
+            size: 10.0,
+            ..Default::default()
+        }];
+        // 每列最多 3 个字，列自右向左
+        let l = layout(&[&font], &spans, 1.5, 30.0);
+        assert_eq!(l.columns, vec![30.0, 20.0]);
+        assert_eq!(l.width, 25.0);
+        assert_eq!(l.height, 30.0);
+        let first = &l.glyphs[0];
+        let fourth = &l.glyphs[3];
+        assert_eq!((first.column, first.x, first.y), (0, 15.0, 0.0));
+        assert_eq!((fourth.column, fourth.x, fourth.y), (1, 0.0, 0.0));
+    }
+}
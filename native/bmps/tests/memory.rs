@@ -0,0 +1,86 @@
+//! 渲染过程的峰值内存。除解码后的照片与输出画布外，中间缓冲不超过 memory_budget
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bmps::config::{self, Config};
+use image::{Rgb, RgbImage};
+
+/// 统计当前与峰值分配字节数的分配器
+struct Counter;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counter {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = System.alloc(layout);
+        if !p.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        p
+    }
+
+    unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+        System.dealloc(p, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counter = Counter;
+
+// 与图像大小无关的固定开销：sRGB 查找表、每个线程的行缓冲、编解码器的状态
+const OVERHEAD: usize = 1 << 20;
+
+#[test]
+fn peak() {
+    let dir = std::env::temp_dir().join("bmps_memory");
+    std::fs::create_dir_all(&dir).unwrap();
+    let (w, h) = (1600, 1200);
+    let src = dir.join("src.png");
+    RgbImage::from_fn(w, h, |x, y| {
+        Rgb([
+            (x * 7 % 251) as u8,
+            (y * 13 % 251) as u8,
+            ((x + y) % 251) as u8,
+        ])
+    })
+    .save(&src)
+    .unwrap();
+    let dest = dir.join("out.png");
+    let budget = 8 << 20;
+    // 小半径整幅分条模糊，大半径缩小后模糊；线性光逐条解码
+    for (blur_radius, linear_light) in [(20, false), (20, true), (80, false), (80, true)] {
+        let cfg = Config {
+            source_file: src.to_string_lossy().into_owned(),
+            dest_file: dest.to_string_lossy().into_owned(),
+            font: None,
+            size: config::Size {
+                width: 4,
+                height: 3,
+                aspect_ratio: true,
+                blur_radius,
+                ..Default::default()
+            },
+            white_bg: false,
+            caption: None,
+            date_stamp: None,
+            memory_budget: Some(budget),
+            linear_light,
+            tone: Default::default(),
+            grain: None,
+            background: Default::default(),
+        };
+        let base = CURRENT.load(Ordering::Relaxed);
+        PEAK.store(base, Ordering::Relaxed);
+        bmps::go(cfg).unwrap();
+        let peak = PEAK.load(Ordering::Relaxed) - base;
+        let (cw, ch) = image::image_dimensions(&dest).unwrap();
+        let photo = (w * h * 3) as usize;
+        let canvas = (cw * ch * 3) as usize;
+        let extra = peak.saturating_sub(photo + canvas);
+        println!("radius {blur_radius} linear {linear_light}: {cw}x{ch}, peak {peak}, beyond photo and canvas {extra}");
+        assert!(extra <= budget + OVERHEAD, "{extra} > {budget}");
+    }
+}
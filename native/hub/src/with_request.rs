@@ -52,13 +52,21 @@ async fn handel_gen(req: messages::bg::GenRequest) -> messages::bg::GenResponse
             blur_radius: req.blur_radius,
             shadow: req.shadow,
             round_radius: req.round_radius,
-            padding: req.padding,
+            padding: bmps::config::Padding::uniform(req.padding),
             aspect_ratio: false,
             ..dft
         },
         source_file: req.source,
         dest_file: req.dest,
         font: None,
+        caption: None,
+        date_stamp: None,
+        // 移动端内存有限，大图分条渲染
+        memory_budget: Some(256 << 20),
+        linear_light: false,
+        tone: Default::default(),
+        grain: None,
+        background: Default::default(),
     };
     crate::debug_print!("params {cfg:?}");
     let res = tokio_with_wasm::tokio::task::spawn_blocking(move || bmps::go(cfg)).await;